@@ -0,0 +1,145 @@
+//! Fluent construction of a `PolicyConfig` without writing JSON.
+//!
+//! The builder assembles the same document a plugin configuration would
+//! carry and loads it through `PolicyConfig::materialize`, so a built policy
+//! is normalized and compiled exactly like a configured one. Unlike a
+//! configured policy, it is refused outright when any rule is invalid.
+
+use crate::PolicyConfig;
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone, Default)]
+pub struct PolicyConfigBuilder {
+    document: Map<String, Value>,
+}
+
+impl PolicyConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Suppresses `path` in bodies, query parameters and headers.
+    pub fn suppress(self, path: &str) -> Self {
+        self.push("suppression_paths", path.into())
+    }
+
+    /// Suppresses the JSON body `path` only when it holds exactly `value`.
+    pub fn suppress_value(self, path: &str, value: impl Into<Value>) -> Self {
+        self.push("suppression_paths", serde_json::json!({ "path": path, "value": value.into() }))
+    }
+
+    pub fn allow(self, path: &str) -> Self {
+        self.push("allow_paths", path.into())
+    }
+
+    /// Allows `path` only with one of `values`.
+    pub fn allow_values(self, path: &str, values: &[&str]) -> Self {
+        self.push("allow_paths", serde_json::json!({ "path": path, "values": values }))
+    }
+
+    pub fn deny_key_name(self, name: &str) -> Self {
+        self.push("deny_key_names", name.into())
+    }
+
+    pub fn deny_value_regex(self, pattern: &str) -> Self {
+        self.push("value_deny_regexes", pattern.into())
+    }
+
+    pub fn require_header(self, name: &str) -> Self {
+        self.push("required_headers", name.into())
+    }
+
+    pub fn block_status(self, status: u32) -> Self {
+        self.set("block_status", status)
+    }
+
+    /// Sets any configuration key the dedicated methods don't cover. `value`
+    /// is written into the JSON document as-is and only checked by `build`.
+    pub fn set(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.document.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Loads the assembled document. A rule that fails to compile, in the
+    /// policy or any sub-policy, fails the build rather than being skipped.
+    pub fn build(self) -> Result<PolicyConfig, String> {
+        let config = PolicyConfig::materialize(Value::Object(self.document))?;
+        if !config.compile_errors.is_empty() {
+            return Err(format!("{} invalid rule(s): {}", config.compile_errors.len(), config.compile_errors.join("; ")));
+        }
+        Ok(config)
+    }
+
+    fn push(mut self, key: &str, value: Value) -> Self {
+        let entry = self.document.entry(key).or_insert_with(|| Value::Array(Vec::new()));
+        if let Value::Array(items) = entry {
+            items.push(value);
+        } else {
+            *entry = Value::Array(vec![value]);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_policy_matches_the_json_form() {
+        let built = PolicyConfigBuilder::new()
+            .suppress("User.Password")
+            .suppress_value("user.role", "admin")
+            .allow("user.name")
+            .allow_values("status", &["active", "inactive"])
+            .deny_key_name("__proto__")
+            .require_header("X-Tenant")
+            .block_status(404)
+            .set("violation_threshold", 2)
+            .build()
+            .unwrap();
+        let parsed = PolicyConfig::load(br#"{
+            "suppression_paths": ["User.Password", {"path": "user.role", "value": "admin"}],
+            "allow_paths": ["user.name", {"path": "status", "values": ["active", "inactive"]}],
+            "deny_key_names": ["__proto__"],
+            "required_headers": ["X-Tenant"],
+            "block_status": 404,
+            "violation_threshold": 2
+        }"#).unwrap();
+
+        assert_eq!(built.suppression_paths, parsed.suppression_paths);
+        assert_eq!(built.suppression_values, parsed.suppression_values);
+        assert_eq!(built.allow_paths, parsed.allow_paths);
+        assert_eq!(built.allow_values, parsed.allow_values);
+        assert_eq!(built.deny_key_names, parsed.deny_key_names);
+        assert_eq!(built.required_headers, parsed.required_headers);
+        assert_eq!((built.block_status, built.violation_threshold), (404, 2));
+    }
+
+    #[test]
+    fn built_policy_inspects_through_the_public_api() {
+        let policy = PolicyConfigBuilder::new().suppress("user.password").build().unwrap();
+
+        let block = policy.inspect_json(&serde_json::json!({ "user": { "password": "hunter2" } })).unwrap_err();
+        assert_eq!(block.defense(), "methylated");
+        assert!(!block.detail().contains("hunter2"));
+        assert!(policy.inspect_json(&serde_json::json!({ "user": { "name": "ada" } })).is_ok());
+    }
+
+    #[test]
+    fn invalid_rules_fail_the_build() {
+        let error = PolicyConfigBuilder::new().deny_value_regex("(unclosed").deny_value_regex("^ok$").build().unwrap_err();
+        assert!(error.starts_with("1 invalid rule(s): (unclosed"), "{}", error);
+
+        let built = PolicyConfigBuilder::new().deny_value_regex("^ok$").build().unwrap();
+        assert!(built.compile_errors.is_empty());
+        assert_eq!(built.value_deny.len(), 1);
+
+        let nested = PolicyConfigBuilder::new()
+            .set("sub_policies", serde_json::json!([{ "match": { "path_prefix": "/a" }, "value_deny_regexes": ["("] }]))
+            .build();
+        assert!(nested.unwrap_err().contains("invalid rule"));
+
+        assert!(PolicyConfigBuilder::new().set("block_status", "not a number").build().is_err());
+    }
+}
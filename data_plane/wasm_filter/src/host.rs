@@ -0,0 +1,115 @@
+//! Host calls that hand every status back instead of trapping.
+//!
+//! The `proxy_wasm::hostcalls` wrappers panic on any status they do not
+//! expect, which aborts the VM. Best-effort and fail-open paths need the
+//! status, so these go straight to the ABI imports and map it themselves.
+
+use proxy_wasm::types::{Status, StreamType};
+
+extern "C" {
+    fn proxy_get_shared_data(key_data: *const u8, key_size: usize,
+        return_value_data: *mut *mut u8, return_value_size: *mut usize, return_cas: *mut u32) -> u32;
+    fn proxy_set_shared_data(key_data: *const u8, key_size: usize, value_data: *const u8, value_size: usize, cas: u32) -> u32;
+    fn proxy_enqueue_shared_queue(queue_id: u32, value_data: *const u8, value_size: usize) -> u32;
+    fn proxy_send_local_response(status_code: u32, status_code_details_data: *const u8, status_code_details_size: usize,
+        body_data: *const u8, body_size: usize, headers_data: *const u8, headers_size: usize, grpc_status: i32) -> u32;
+    fn proxy_close_stream(stream_type: StreamType) -> u32;
+}
+
+/// A raw ABI status as a `Result`. Codes outside the ABI are reported as
+/// `InternalFailure`.
+fn status(code: u32) -> Result<(), Status> {
+    match code {
+        0 => Ok(()),
+        1 => Err(Status::NotFound),
+        2 => Err(Status::BadArgument),
+        3 => Err(Status::SerializationFailure),
+        4 => Err(Status::ParseFailure),
+        7 => Err(Status::Empty),
+        8 => Err(Status::CasMismatch),
+        _ => Err(Status::InternalFailure),
+    }
+}
+
+/// The value and CAS token stored under `key`; an absent key is `(None, None)`.
+pub(crate) fn get_shared_data(key: &str) -> Result<(Option<Vec<u8>>, Option<u32>), Status> {
+    let mut data: *mut u8 = std::ptr::null_mut();
+    let mut size: usize = 0;
+    let mut cas: u32 = 0;
+    match status(unsafe { proxy_get_shared_data(key.as_ptr(), key.len(), &mut data, &mut size, &mut cas) }) {
+        Ok(()) => {
+            // The host allocated the value through `proxy_on_memory_allocate`; it is ours to free
+            let value = (!data.is_null()).then(|| unsafe { Vec::from_raw_parts(data, size, size) });
+            Ok((value, (cas != 0).then_some(cas)))
+        }
+        Err(Status::NotFound) => Ok((None, None)),
+        Err(status) => Err(status),
+    }
+}
+
+/// Writes `key`; a `cas` of `None` overwrites unconditionally.
+pub(crate) fn set_shared_data(key: &str, value: Option<&[u8]>, cas: Option<u32>) -> Result<(), Status> {
+    status(unsafe {
+        proxy_set_shared_data(key.as_ptr(), key.len(),
+            value.map_or(std::ptr::null(), <[u8]>::as_ptr), value.map_or(0, <[u8]>::len), cas.unwrap_or(0))
+    })
+}
+
+pub(crate) fn enqueue_shared_queue(queue_id: u32, value: &[u8]) -> Result<(), Status> {
+    status(unsafe { proxy_enqueue_shared_queue(queue_id, value.as_ptr(), value.len()) })
+}
+
+/// Answers the current request locally with `status`, `headers` and `body`.
+pub(crate) fn send_http_response(status_code: u32, headers: &[(&str, &str)], body: Option<&[u8]>) -> Result<(), Status> {
+    let headers = serialize_map(headers);
+    status(unsafe {
+        proxy_send_local_response(status_code, std::ptr::null(), 0,
+            body.map_or(std::ptr::null(), <[u8]>::as_ptr), body.map_or(0, <[u8]>::len),
+            headers.as_ptr(), headers.len(), -1)
+    })
+}
+
+pub(crate) fn reset_http_request() -> Result<(), Status> {
+    status(unsafe { proxy_close_stream(StreamType::HttpRequest) })
+}
+
+/// The ABI's header map encoding: the pair count, every name and value
+/// length, then each name and value NUL-terminated, all lengths as LE u32.
+fn serialize_map(map: &[(&str, &str)]) -> Vec<u8> {
+    let mut bytes = (map.len() as u32).to_le_bytes().to_vec();
+    for (name, value) in map {
+        bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    }
+    for (name, value) in map {
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.push(0);
+        bytes.extend_from_slice(value.as_bytes());
+        bytes.push(0);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_status_code_maps_without_panicking() {
+        assert_eq!(status(0), Ok(()));
+        assert_eq!(status(1), Err(Status::NotFound));
+        assert_eq!(status(8), Err(Status::CasMismatch));
+        assert_eq!(status(10), Err(Status::InternalFailure));
+        assert_eq!(status(u32::MAX), Err(Status::InternalFailure));
+    }
+
+    #[test]
+    fn header_maps_use_the_abi_layout() {
+        assert_eq!(serialize_map(&[]), [0, 0, 0, 0]);
+        assert_eq!(serialize_map(&[("a", "bc")]), [
+            1, 0, 0, 0,
+            1, 0, 0, 0, 2, 0, 0, 0,
+            b'a', 0, b'b', b'c', 0,
+        ]);
+    }
+}
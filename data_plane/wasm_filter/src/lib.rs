@@ -1,187 +1,6414 @@
+// Without the `plugin` entry point only the inspection API is reachable
+#![cfg_attr(not(feature = "plugin"), allow(dead_code))]
+
 use proxy_wasm::traits::*;
 use proxy_wasm::types::*;
+use regex::Regex;
 use serde::Deserialize;
-use std::collections::HashSet;
-use log::{info, warn};
+use unicode_normalization::UnicodeNormalization;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::rc::Rc;
+use log::{debug, info, warn};
+
+mod builder;
+mod host;
+mod parsers;
+mod rng;
+pub use builder::PolicyConfigBuilder;
+use parsers::{form_fields, parser_for};
+use rng::Rng;
 
 // -----------------------------------------------------------------------------
 // 1. Data Structures (Genetic Memory)
 // -----------------------------------------------------------------------------
 
-#[derive(Deserialize, Debug, Clone, Default)]
-struct PolicyConfig {
+#[derive(Deserialize, Debug, Clone)]
+pub struct PolicyConfig {
+    #[serde(default, rename = "suppression_paths")]
+    suppression_entries: Vec<SuppressionEntry>, // R_epi: Methylation targets (paths, optionally with a value)
+    #[serde(default)]
+    required_headers: HashSet<String>,  // Request headers that must be present
+    #[serde(default)]
+    strip_headers: HashSet<String>,     // Internal headers removed before forwarding, never blocked
+    #[serde(default)]
+    deny_key_names: HashSet<String>,    // Bare key names denied at any depth, case-insensitively
+    #[serde(default)]
+    suppression_globs: Vec<String>,     // Dotted path globs, e.g. `config.*`; `**` spans segments
+    #[serde(default)]
+    suppression_glob_exceptions: Vec<String>, // Globs carved out of suppression_globs; most specific wins
+    #[serde(default)]
+    violation_threshold: usize,         // Distinct suppressed paths stripped instead of blocked
+    #[serde(default)]
+    evaluation_order: EvaluationOrder,  // Whether an exact allow_paths entry overrides body suppression
+    #[serde(default, rename = "allow_paths")]
+    allow_entries: Vec<AllowEntry>,     // M_star: Whitelist (names, optionally with permitted values)
+    #[serde(default = "default_allow_prefix_depth")]
+    allow_prefix_depth: Option<usize>,  // Descendant levels an allowed parent covers; null = any
+    #[serde(default)]
+    allow_globs: Vec<String>,           // Dotted path globs allowed alongside allow_paths, e.g. `profile.**`
+    #[serde(default)]
+    required_paths: HashSet<String>,    // Strict allowlist: paths every body must carry; allowed implicitly
+    #[serde(default)]
+    scrub_unallowed: bool,              // Strict allowlist: drop foreign paths instead of blocking
+    #[serde(default = "default_true")]
+    preserve_key_order: bool,           // Rewritten JSON keeps the client's key order; false sorts keys
+    #[serde(default = "default_true")]
+    minify: bool,                       // Rewritten JSON is compact; false pretty-prints it
+    #[serde(default)]
+    allowed_auth_schemes: HashSet<String>, // Empty = any scheme accepted
+    #[serde(default)]
+    max_json_bytes: Option<usize>,      // Bound on the re-serialized (logical) payload
+    #[serde(default)]
+    response_redact_paths: HashSet<String>, // JSON response paths masked before reaching the client
+    #[serde(default)]
+    response_redact_statuses: HashSet<u32>, // Only these response statuses are redacted; empty = all
+    #[serde(default = "default_max_decompressed_bytes")]
+    max_decompressed_bytes: usize,      // Larger compressed bodies are not decoded
+    #[serde(default)]
+    require_json_object: bool,          // Reject bare arrays, strings, numbers at the top level
+    #[serde(default)]
+    reject_duplicate_keys: bool,        // An object repeating a key is ambiguous across parsers
+    #[serde(default)]
+    reject_unsafe_numbers: bool,        // Integers beyond 2^53 lose precision in other parsers
+    #[serde(default)]
+    reject_control_chars: bool,         // Control characters other than tab/CR/LF in keys or strings
+    #[serde(default)]
+    paranoid: bool,                     // All of the above, plus fail-closed parsing (see `materialize`)
+    #[serde(default)]
+    max_array_depth: Option<usize>,     // Arrays nested in arrays; objects in between don't count
+    #[serde(default)]
+    max_values: Option<usize>,          // String values per body; bounds the cost of value scans
+    #[serde(default)]
+    array_value_deny: HashMap<String, HashSet<String>>, // Array path -> forbidden elements
+    #[serde(default)]
+    grpc_mode: GrpcMode,
+    #[serde(default)]
+    grpc_web_text_scan: bool,           // Decode grpc-web-text bodies and check trailer-frame metadata
+    #[serde(default = "default_true")]
+    passthrough_options: bool,          // CORS preflight skips body buffering
+    #[serde(default)]
+    streaming_inspection: bool,         // Scan each buffered chunk for suppressed keys
+    #[serde(default)]
+    buffer_cap_bytes: Option<usize>,    // Hard cap protecting WASM memory
+    #[serde(default)]
+    on_buffer_overflow: OverflowAction,
+    #[serde(default)]
+    buffering_mode: BufferingMode,      // Hold headers until the body is inspected, or forward them early
+    #[serde(default)]
+    block_action: BlockAction,
+    #[serde(default = "default_block_status")]
+    block_status: u32,                  // HTTP status for `deny` blocks
+    #[serde(default)]
+    block_as_not_found: bool,           // Suppression and route blocks answer a bare 404
+    #[serde(default = "default_grpc_block_status")]
+    grpc_block_status: u32,             // grpc-status sent when blocking gRPC calls
+    #[serde(default)]
+    reveal_match: bool,                 // Disclose request paths / matched rules (privacy toggle)
+    #[serde(default)]
+    log_value_snippet: ValueSnippet,
+    #[serde(default = "default_max_report_bytes")]
+    max_report_bytes: usize,            // Cap on any path, rule or value echoed to logs, records or replies
+    #[serde(default)]
+    summary_log: bool,                  // One consolidated info line per request
+    #[serde(default)]
+    decision_log: bool,                 // Final decision logged from on_log, with the request id
+    #[serde(default)]
+    debug_decision_header: bool,        // Stamp x-leukocyte-decision on traffic; testing only, it leaks policy
+    #[serde(default)]
+    inspection_latency_metric: bool,    // Time body inspection into leukocyte_inspection_ms
+    #[serde(default)]
+    plain_logs: bool,                   // Omit emoji prefixes for log pipelines that choke on them
+    #[serde(default)]
+    block_body_json: bool,              // JSON error envelope instead of plaintext
+    #[serde(default)]
+    block_cors: Option<BlockCors>,
+    #[serde(default = "default_sample_rate")]
+    body_inspection_sample: f64,        // Fraction of bodies deeply inspected (0.0-1.0)
+    #[serde(default)]
+    rng_seed: u64,                      // Mixed with the context id; pin for reproducible sampling
+    #[serde(default)]
+    property_rules: Vec<PropertyRule>,  // Evaluated in order; first match decides
+    #[serde(default)]
+    min_tls_version: Option<String>,    // e.g. "TLSv1.2"
+    #[serde(default)]
+    allowed_protocols: HashSet<String>, // `request.protocol` values, e.g. "HTTP/1.1", "HTTP/2"
+    #[serde(default = "default_true")]
+    allow_plaintext: bool,              // Non-TLS connections pass TLS gating
+    #[serde(default)]
+    sni_allow: HashSet<String>,         // Empty = any SNI
+    #[serde(default)]
+    sni_deny: HashSet<String>,
+    #[serde(default)]
+    allowed_hosts: HashSet<String>,     // Host/:authority names, port ignored; `*.example.com` for subdomains
+    #[serde(default)]
+    require_origin: bool,               // Mutating methods need an allowed Origin (or Referer)
+    #[serde(default)]
+    allowed_origins: HashSet<String>,   // `scheme://host[:port]`, e.g. `https://app.example.com`; `null` opts in
+    #[serde(default)]
+    reject_double_encoding: bool,       // Block %25XX tricks in :path and query params
+    #[serde(default)]
+    suppression_path_segments: HashSet<String>, // Decoded :path segments denied anywhere, e.g. `..`, `admin`
+    #[serde(default)]
+    max_uri_length: Option<usize>,      // Bytes of :path, query string included
+    #[serde(default)]
+    merge_query_params: bool,           // Inspect decoded query params as body paths under `query.`
+    #[serde(default)]
+    uri_length_form: UriLengthForm,     // Whether max_uri_length counts the raw or decoded :path
+    #[serde(default)]
+    normalize_path: bool,               // Canonicalize :path before route-based matching
+    #[serde(default)]
+    normalize_header_names: bool,       // NFKC-fold non-ASCII header names before suppression
+    #[serde(default)]
+    suppression_header_regexes: Vec<String>, // Whole lowercased header names, e.g. `x-.*-internal`
+    #[serde(default)]
+    header_value_deny_regexes: HashMap<String, Vec<String>>, // Header name -> patterns for each occurrence's value
+    #[serde(default)]
+    match_joined_header_values: bool,   // Also match repeated headers' values joined as one field
+    #[serde(default)]
+    value_deny_regexes: Vec<String>,    // Applied to JSON string values and (opt-in) CSV cells
+    #[serde(default)]
+    value_allow_regex: Option<String>,  // Every JSON string value must match it whole
+    #[serde(default)]
+    body_raw_deny_regexes: Vec<String>, // Matched against the raw body text before any parsing
+    #[serde(default)]
+    body_hash_denylist: HashSet<String>, // Hex SHA-256 of known-bad bodies, exact bytes
+    #[serde(default = "default_raw_scan_bytes")]
+    raw_scan_max_bytes: usize,          // Only this much of the body is scanned raw
+    #[serde(default)]
+    csv_scan_values: bool,
+    #[serde(default)]
+    multipart_scan_values: bool,        // Also run value_deny_regexes over text form fields
+    #[serde(default)]
+    max_multipart_file_bytes: Option<usize>, // File parts are size-checked, never parsed
+    #[serde(default)]
+    enforce_content_type: bool,         // Block bodies that contradict their declared content-type
+    #[serde(default = "default_true")]
+    inspect_body: bool,                 // false = headers-only mode, bodies never buffered
+    #[serde(default = "default_true")]
+    inspect_headers: bool,              // false = body-only mode, header rules left upstream
+    #[serde(default = "default_allow")]
+    default_action: RuleAction,         // Posture for requests no allow rule matched
+    #[serde(default)]
+    composite_rules: Vec<CompositeRule>, // AND-combined body conditions; first match decides
+    #[serde(default)]
+    rule_groups: Vec<RuleGroup>,        // Named boolean trees; first match decides
+    #[serde(default)]
+    allowed_route_patterns: Vec<String>, // Globs (`*` one segment, `**` any depth); others are blocked
+    #[serde(default)]
+    bypass_paths: HashSet<String>,      // Exact routes, or prefixes ending in `*`
+    #[serde(default)]
+    header_names: HeaderNames,          // Where the host exposes method, path and host
+    #[serde(default)]
+    inspect_prefix_bytes: Option<usize>, // Only the first N body bytes are examined
+    #[serde(default)]
+    on_unparseable: Option<UnparseableAction>, // Default: Block with allow_paths, else Allow
+    #[serde(default)]
+    non_json_action: Option<NonJsonAction>, // Takes precedence over on_unparseable when set
+    #[serde(default)]
+    empty_body_action: EmptyBodyAction, // Strict allowlist only: whether a body is required
+    #[serde(default)]
+    trusted_proxies: Vec<String>,       // CIDRs whose x-forwarded-for is believed
+    #[serde(default)]
+    rate_limit: Option<RateLimit>,      // Per client IP, shared across workers
+    #[serde(default)]
+    rate_limit_response: RateLimitResponse, // Reply to rate-limited requests; block_* settings never apply
+    #[serde(default)]
+    send_failure_action: SendFailureAction, // When the host rejects a local reply (block or admin route)
+    #[serde(default)]
+    shared_data_failure: SharedDataFailure, // When shared data errors, for gates that depend on it
+    #[serde(default)]
+    recent_blocks: Option<RecentBlocks>, // Last N block summaries, served as JSON on an admin route
+    #[serde(default)]
+    self_test: Option<SelfTest>,        // Magic header answered with the loaded policy's status
+    #[serde(default)]
+    incident_queue: Option<String>,     // Shared queue receiving a JSON record per block
+    #[serde(default)]
+    config_queue: Option<String>,       // Shared queue watched for pushed policies
+    #[serde(default)]
+    defaults: serde_json::Map<String, serde_json::Value>, // Fields every sub-policy inherits
+    #[serde(default)]
+    sub_policies: Vec<serde_json::Map<String, serde_json::Value>>, // `match` plus overrides of `defaults`
+
+    // Compiled in `on_configure`
+    #[serde(skip)]
+    value_deny: Vec<Regex>,
+    #[serde(skip)]
+    header_value_deny: HashMap<String, Vec<Regex>>,
+    #[serde(skip)]
+    value_allow: Option<Regex>,
+    #[serde(skip)]
+    body_raw_deny: Vec<Regex>,
+    #[serde(skip)]
+    header_deny: Vec<Regex>,
+    #[serde(skip)]
+    glob_rules: Vec<GlobRule>,
+    #[serde(skip)]
+    suppression_paths: HashSet<String>,
+    #[serde(skip)]
+    suppression_values: HashMap<String, Vec<serde_json::Value>>, // Path -> values that trigger it
+    #[serde(skip)]
+    suppression_types: HashMap<String, ValueType>, // Path -> the only type that triggers it
+    #[serde(skip)]
+    suppression_depths: Vec<(String, usize)>, // Trailing path -> the only segment depth it fires at
+    #[serde(skip)]
+    allow_types: HashMap<String, ValueType>, // Path -> the only type it is allowed with
+    #[serde(skip)]
+    allow_paths: HashSet<String>,
+    #[serde(skip)]
+    allow_values: HashMap<String, HashSet<String>>, // Path -> permitted scalar values
+    #[serde(skip)]
+    allow_arrays: HashSet<String>,      // Allowed paths whose array elements pass whole
+    #[serde(skip)]
+    trusted_nets: Vec<Cidr>,
+    #[serde(skip)]
+    allowed_routes: Vec<Regex>,
+    #[serde(skip)]
+    allowed_globs: Vec<Regex>,
+    #[serde(skip)]
+    resolved_sub_policies: Vec<SubPolicy>,
+    #[serde(skip)]
+    compile_errors: Vec<String>,        // Rules skipped by `compile`, kept for `config_status`
+}
+
+/// An `allow_paths` entry: a bare path, or a path whose value must be one of
+/// `values`, e.g. `{"path": "status", "values": ["active", "inactive"]}`.
+/// With `"array": true`, an array at the path admits its elements whole:
+/// nothing inside them needs its own entry, and `values` applies per element.
+/// `expected_type` rejects a value of any other JSON type. `"enabled": false`
+/// keeps the entry in the configuration without compiling it. An unknown key
+/// fails the load, so a misspelt constraint cannot leave the path open.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+enum AllowEntry {
+    Path(String),
+    Constrained(AllowConstraint),
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct AllowConstraint {
+    path: String,
+    #[serde(default)]
+    values: Option<Vec<String>>,
     #[serde(default)]
-    suppression_paths: HashSet<String>, // R_epi: Methylation targets
+    array: bool,
     #[serde(default)]
-    allow_paths: HashSet<String>,       // M_star: Whitelist
+    expected_type: ValueType,
+    #[serde(default = "default_true")]
+    enabled: bool,
 }
 
-struct LeukocyteRoot {
-    config: PolicyConfig,
+impl AllowEntry {
+    fn enabled(&self) -> bool {
+        !matches!(self, AllowEntry::Constrained(AllowConstraint { enabled: false, .. }))
+    }
 }
 
-impl Context for LeukocyteRoot {}
+/// A compiled `suppression_globs` or `suppression_glob_exceptions` entry.
+#[derive(Debug, Clone)]
+struct GlobRule {
+    glob: String,
+    regex: Regex,
+    specificity: usize, // Literal characters in the glob; the more, the more specific
+    exception: bool,
+}
 
-impl RootContext for LeukocyteRoot {
-    fn on_configure(&mut self, _plugin_configuration_size: usize) -> bool {
-        if let Some(config_bytes) = self.get_plugin_configuration() {
-            if let Ok(config_str) = std::str::from_utf8(&config_bytes) {
-                if let Ok(mut config) = serde_json::from_str::<PolicyConfig>(config_str) {
-                    // Normalize to lowercase for header matching (Envoy uses lowercase headers)
-                    config.suppression_paths = config.suppression_paths.into_iter()
-                        .map(|s| s.to_lowercase())
-                        .collect();
-                    config.allow_paths = config.allow_paths.into_iter()
-                        .map(|s| s.to_lowercase())
-                        .collect();
+impl GlobRule {
+    fn new(glob: &str, exception: bool) -> Result<Self, regex::Error> {
+        let glob = glob.to_lowercase();
+        Ok(GlobRule {
+            regex: path_glob_to_regex(&glob)?,
+            specificity: glob.chars().filter(|c| !matches!(c, '*' | '?')).count(),
+            glob,
+            exception,
+        })
+    }
+}
 
-                    info!("🧬 [Leukocyte] Configuration Transduced: {} suppression paths, {} allow paths", 
-                          config.suppression_paths.len(), config.allow_paths.len());
-                    self.config = config;
-                    return true;
-                }
+/// A `suppression_paths` entry: a bare path, suppressed whatever its value,
+/// or a JSON body path suppressed only with one exact value, e.g.
+/// `{"path": "user.role", "value": "admin"}`, or only when its value has one
+/// JSON type, e.g. `{"path": "id", "expected_type": "string"}`. With a
+/// `depth`, e.g. `{"path": "token", "depth": 1}`, `path` names the trailing
+/// segments of any flattened path that has exactly `depth` segments, so
+/// depth 1 is top-level only and depth 2 is `*.token`. Object forms
+/// take `"enabled": false` to stay configured but uncompiled, and
+/// `{"path": ...}` alone behaves like the bare path. An object takes at most
+/// one constraint; an unknown key, a mistyped constraint or a second one
+/// fails the load rather than quietly widening the rule.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "RawSuppressionEntry")]
+enum SuppressionEntry {
+    Path(String),
+    Valued {
+        path: String,
+        value: serde_json::Value,
+        enabled: bool,
+    },
+    AtDepth {
+        path: String,
+        depth: usize,
+        enabled: bool,
+    },
+    Typed {
+        path: String,
+        expected_type: ValueType,
+        enabled: bool,
+    },
+}
+
+/// A `suppression_paths` entry as written, before its constraint is checked.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawSuppressionEntry {
+    Path(String),
+    Object(SuppressionObject),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SuppressionObject {
+    path: String,
+    #[serde(default, deserialize_with = "present_value")]
+    value: Option<serde_json::Value>, // `Some(Null)` for an explicit `"value": null`
+    #[serde(default)]
+    depth: Option<usize>,
+    #[serde(default)]
+    expected_type: Option<ValueType>,
+    #[serde(default = "default_true")]
+    enabled: bool,
+}
+
+fn present_value<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<serde_json::Value>, D::Error> {
+    serde_json::Value::deserialize(deserializer).map(Some)
+}
+
+impl TryFrom<RawSuppressionEntry> for SuppressionEntry {
+    type Error = String;
+
+    fn try_from(raw: RawSuppressionEntry) -> Result<Self, String> {
+        let SuppressionObject { path, value, depth, expected_type, enabled } = match raw {
+            RawSuppressionEntry::Path(path) => return Ok(SuppressionEntry::Path(path)),
+            RawSuppressionEntry::Object(object) => object,
+        };
+        match (value, depth, expected_type) {
+            (Some(value), None, None) => Ok(SuppressionEntry::Valued { path, value, enabled }),
+            (None, Some(depth), None) => Ok(SuppressionEntry::AtDepth { path, depth, enabled }),
+            (None, None, expected_type) => {
+                Ok(SuppressionEntry::Typed { path, expected_type: expected_type.unwrap_or_default(), enabled })
             }
+            _ => Err(format!("suppression entry {:?} combines value, depth and expected_type; use one per entry", path)),
         }
-        warn!("⚠️ [Leukocyte] Failed to load configuration (Immunity Deficit)");
-        true
     }
+}
 
-    fn create_http_context(&self, _context_id: u32) -> Option<Box<dyn HttpContext>> {
-        Some(Box::new(LeukocyteFilter {
-            config: self.config.clone(),
-        }))
+impl SuppressionEntry {
+    fn enabled(&self) -> bool {
+        match self {
+            SuppressionEntry::Path(_) => true,
+            SuppressionEntry::Valued { enabled, .. }
+            | SuppressionEntry::AtDepth { enabled, .. }
+            | SuppressionEntry::Typed { enabled, .. } => *enabled,
+        }
     }
+}
 
-    fn get_type(&self) -> Option<ContextType> {
-        Some(ContextType::HttpContext)
+/// `raw` bounds what the proxy and upstream buffer; `decoded` bounds what
+/// the application sees, so padding with `%XX` escapes cannot stretch it.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+enum UriLengthForm {
+    #[default]
+    Raw,
+    Decoded,
+}
+
+/// Which of suppression and the allowlist wins when a body path is in both.
+///
+/// `SuppressFirst` (the default) fails closed: a suppression rule always
+/// fires, so a broad or mistaken allow entry can never reopen a pathogen
+/// path. `AllowFirst` trusts the allowlist: a path listed exactly in
+/// `allow_paths` skips every body suppression rule (exact, valued, typed,
+/// globs, key names), so a wrong allow entry silently disables suppression
+/// for that path. Ancestor coverage via `allow_prefix_depth` never counts as
+/// explicit, and value rules (`value_deny_regexes` etc.) apply either way.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+enum EvaluationOrder {
+    #[default]
+    SuppressFirst,
+    AllowFirst,
+}
+
+/// The JSON type a typed suppression or allow entry applies to. Array
+/// elements are judged one by one, as they flatten under their parent key.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+enum ValueType {
+    String,
+    Number,
+    Bool,
+    #[default]
+    Any,
+}
+
+impl ValueType {
+    fn admits(self, value: &serde_json::Value) -> bool {
+        match self {
+            ValueType::String => value.is_string(),
+            ValueType::Number => value.is_number(),
+            ValueType::Bool => value.is_boolean(),
+            ValueType::Any => true,
+        }
     }
 }
 
-struct LeukocyteFilter {
-    config: PolicyConfig,
+/// Header names carrying the request's method, path and host. Defaults are
+/// the HTTP/2 pseudo-headers Envoy provides; other hosts may differ.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct HeaderNames {
+    #[serde(default = "default_method_header")]
+    method: String,
+    #[serde(default = "default_path_header")]
+    path: String,
+    #[serde(default = "default_host_header")]
+    host: String,
 }
 
-impl Context for LeukocyteFilter {}
+impl Default for HeaderNames {
+    fn default() -> Self {
+        HeaderNames { method: default_method_header(), path: default_path_header(), host: default_host_header() }
+    }
+}
 
-impl LeukocyteFilter {
-    fn send_denial(&mut self, reason: &str, defense_type: &str) {
-        let headers = self.get_http_request_headers();
-        let is_grpc = headers.iter().any(|(k, v)| k.to_lowercase() == "content-type" && v.to_lowercase().contains("application/grpc"));
+fn default_method_header() -> String {
+    ":method".to_string()
+}
 
-        if is_grpc {
-            self.send_http_response(
-                200,
-                vec![
-                    ("content-type", "application/grpc"),
-                    ("x-leukocyte-defense", defense_type),
-                    ("grpc-status", "7"), // PermissionDenied
-                    ("grpc-message", reason),
-                ],
-                None,
-            );
-        } else {
-             self.send_http_response(
-                403,
-                vec![("x-leukocyte-defense", defense_type)],
-                Some(reason.as_bytes()),
-            );
-        }
+fn default_path_header() -> String {
+    ":path".to_string()
+}
+
+fn default_host_header() -> String {
+    ":authority".to_string()
+}
+
+/// Which requests a sub-policy governs; every given criterion must hold.
+/// An empty match covers all requests.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+struct PolicyMatch {
+    #[serde(default)]
+    path_prefix: Option<String>,
+    #[serde(default)]
+    host: Option<String>,               // Compared without the port, case-insensitively
+    #[serde(default)]
+    methods: HashSet<String>,
+}
+
+impl PolicyMatch {
+    fn matches(&self, request: &RequestInfo) -> bool {
+        let host = request.host.as_deref().map(host_name);
+        self.path_prefix.as_deref().is_none_or(|prefix| request.route.starts_with(prefix))
+            && self.host.as_deref().is_none_or(|expected| host.is_some_and(|h| h.eq_ignore_ascii_case(expected)))
+            && (self.methods.is_empty()
+                || request.method.as_deref().is_some_and(|m| self.methods.iter().any(|allowed| allowed.eq_ignore_ascii_case(m))))
     }
 }
 
-impl HttpContext for LeukocyteFilter {
+#[derive(Debug, Clone)]
+struct SubPolicy {
+    matcher: PolicyMatch,
+    policy: Rc<PolicyConfig>,
+}
 
-    fn on_http_request_headers(&mut self, _num_headers: usize, _end_of_stream: bool) -> Action {
-        let headers = self.get_http_request_headers();
-        for (name, _value) in headers {
-            if self.config.suppression_paths.contains(&name) || 
-               self.config.suppression_paths.contains(&name.to_lowercase()) {
-                warn!("🛡️ [Methylation] Suppressed expression of pathogen header: {}", name);
-                self.send_denial("Access Denied: Pathogen Header Suppressed", "methylated-header");
-                return Action::Pause;
-            }
-        }
+/// A rule on an Envoy attribute such as `request.protocol` or
+/// `connection.tls_version`, resolved through `get_property`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct PropertyRule {
+    path: String,
+    #[serde(flatten)]
+    condition: PropertyCondition,
+    action: RuleAction,
+}
 
-        if !self.config.suppression_paths.is_empty() || !self.config.allow_paths.is_empty() {
-            return Action::Continue;
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum PropertyCondition {
+    Equals(String),
+    /// Also holds when the property is absent.
+    NotEquals(String),
+}
+
+/// Fires only when every condition holds against the same body.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct CompositeRule {
+    all_of: Vec<PathCondition>,
+    action: RuleAction,
+}
+
+/// A body path that must be present and, if `value` is given, equal to it.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct PathCondition {
+    path: String,
+    #[serde(default)]
+    value: Option<serde_json::Value>,
+}
+
+impl PathCondition {
+    fn holds(&self, body: &serde_json::Value) -> bool {
+        let found = values_at_path(body, &self.path);
+        match &self.value {
+            None => !found.is_empty(),
+            Some(expected) => found.contains(&expected),
         }
-        Action::Continue
     }
+}
 
-    fn on_http_request_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
-        if !end_of_stream {
-            return Action::Pause;
-        }
+/// A named boolean expression over request attributes and body paths.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct RuleGroup {
+    name: String,
+    #[serde(default)]
+    severity: Severity,
+    when: Condition,
+    action: RuleAction,
+}
 
-        if let Some(body_bytes) = self.get_http_request_body(0, body_size) {
-            if let Ok(json_body) = serde_json::from_slice::<serde_json::Value>(&body_bytes) {
-                let flat_paths = flatten_json(&json_body, "");
-                
-                // 1. Epigenetic Suppression
-                for param in &flat_paths {
-                    if self.config.suppression_paths.contains(param) {
-                        warn!("🛡️ [Methylation] Suppressed expression of pathogen path: {}", param);
-                        self.send_denial("Access Denied: Pathogen Suppressed", "methylated");
-                        return Action::Pause;
-                    }
-                }
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Severity {
+    Low,
+    #[default]
+    Medium,
+    High,
+    Critical,
+}
 
-                // 2. Hierarchical Purity
-                if !self.config.allow_paths.is_empty() {
-                     for param in &flat_paths {
-                        if !self.config.allow_paths.contains(param) {
-                             warn!("⚔️ [Immunity] Foreign antigen detected (Not in Allow Map): {}", param);
-                             self.send_denial("Access Denied: Foreign Antigen", "antigen-rejected");
-                             return Action::Pause;
-                        }
-                    }
-                }
+/// Expression tree node. `attribute` is one of `method`, `path` or `host`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+enum Condition {
+    AllOf { all_of: Vec<Condition> },
+    AnyOf { any_of: Vec<Condition> },
+    Attribute { attribute: String, equals: String },
+    Path(PathCondition),
+}
+
+impl Condition {
+    fn holds(&self, request: &RequestInfo, body: Option<&serde_json::Value>) -> bool {
+        match self {
+            Condition::AllOf { all_of } => all_of.iter().all(|c| c.holds(request, body)),
+            Condition::AnyOf { any_of } => any_of.iter().any(|c| c.holds(request, body)),
+            Condition::Attribute { attribute, equals } => {
+                let actual = match attribute.as_str() {
+                    "method" => request.method.as_deref(),
+                    "path" => Some(request.route.as_str()),
+                    "host" => request.host.as_deref(),
+                    _ => None,
+                };
+                actual.is_some_and(|a| a.eq_ignore_ascii_case(equals))
             }
+            Condition::Path(path) => body.is_some_and(|b| path.holds(b)),
         }
+    }
+}
 
-        Action::Continue
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum RuleAction {
+    Allow,
+    Deny,
+}
+
+fn default_sample_rate() -> f64 {
+    1.0
+}
+
+/// CORS headers attached to block responses so browsers can read the status.
+/// An `allow_origin` of `*` mirrors the request's `origin` when one is present.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct BlockCors {
+    allow_origin: String,
+    #[serde(default)]
+    allow_methods: Option<String>,
+    #[serde(default)]
+    allow_headers: Option<String>,
+}
+
+/// Opt-in, masked excerpt of the value that tripped a value-based rule.
+/// Path-only rules never log values.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct ValueSnippet {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default = "default_snippet_len")]
+    max_len: usize,
+}
+
+impl Default for ValueSnippet {
+    fn default() -> Self {
+        ValueSnippet { enabled: false, max_len: default_snippet_len() }
     }
 }
 
-// -----------------------------------------------------------------------------
-// Helper: Flatten JSON (The transcription process)
-// -----------------------------------------------------------------------------
-fn flatten_json(value: &serde_json::Value, prefix: &str) -> Vec<String> {
-    let mut paths = Vec::new();
-    
-    match value {
-        serde_json::Value::Object(map) => {
-            for (k, v) in map {
-                let new_key = if prefix.is_empty() {
-                    k.clone()
-                } else {
-                    format!("{}.{}", prefix, k)
-                };
-                paths.push(new_key.clone());
-                paths.extend(flatten_json(v, &new_key));
-            }
-        }
-        serde_json::Value::Array(arr) => {
-             // Treat array indices as separate paths? Or ignore?
-             // Common practice: flatten with [i] or just recurse.
-             // Simpler for this demo: just recurse into objects
-             for v in arr {
-                 paths.extend(flatten_json(v, prefix));
-             }
-        }
-        _ => {}
+fn default_snippet_len() -> usize {
+    16
+}
+
+fn default_allow_prefix_depth() -> Option<usize> {
+    Some(0)
+}
+
+fn default_max_report_bytes() -> usize {
+    256
+}
+
+fn default_max_decompressed_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_raw_scan_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_block_status() -> u32 {
+    403
+}
+
+fn default_grpc_block_status() -> u32 {
+    7 // PERMISSION_DENIED
+}
+
+/// When request headers are released upstream while a body is inspected.
+/// Bodies are buffered until end of stream in both modes.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum BufferingMode {
+    /// Headers are held until the body verdict. Upstream never sees a request
+    /// that is later blocked, at the cost of connection setup starting only
+    /// after the whole body has arrived. `Expect: 100-continue` uploads are
+    /// the exception; see `headers_action`.
+    Pause,
+    /// Headers go upstream immediately so connection setup overlaps body
+    /// buffering; a later block resets an already-started upstream request.
+    #[default]
+    Stream,
+}
+
+/// What to do once a body outgrows `buffer_cap_bytes`. This protects the
+/// sandbox's memory and is distinct from any policy rejection.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum OverflowAction {
+    #[default]
+    Block,
+    /// Stop buffering and forward the remainder uninspected.
+    Pass,
+}
+
+fn default_allow() -> RuleAction {
+    RuleAction::Allow
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for PolicyConfig {
+    /// The empty configuration, so serde field defaults remain the single source of truth.
+    fn default() -> Self {
+        serde_json::from_str("{}").expect("empty policy config must deserialize")
     }
-    paths
 }
 
-// -----------------------------------------------------------------------------
-// Entry Point
-// -----------------------------------------------------------------------------
-proxy_wasm::main! {{
-    proxy_wasm::set_log_level(LogLevel::Trace);
-    proxy_wasm::set_root_context(|_| -> Box<dyn RootContext> {
-        Box::new(LeukocyteRoot {
-            config: PolicyConfig::default(),
+/// Paths removed from an admitted body before it is forwarded.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct StrippedPaths {
+    tolerated: Vec<String>, // Suppressed paths within `violation_threshold`
+    scrubbed: Vec<String>,  // Outermost paths outside the allowlist, under `scrub_unallowed`
+}
+
+/// How gRPC traffic is treated. Its body is length-prefixed protobuf frames,
+/// so JSON body inspection never applies to it.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum GrpcMode {
+    /// Header rules only; the framed body passes untouched.
+    #[default]
+    Bypass,
+    /// Header rules, also applied to request trailers.
+    HeadersAndTrailers,
+}
+
+/// How a block is surfaced to HTTP (non-gRPC) clients.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum BlockAction {
+    /// 403 with the defense header and a plaintext reason.
+    #[default]
+    Deny,
+    /// Send browsers to an error page instead of a raw 403.
+    Redirect {
+        location: String,
+        #[serde(default = "default_redirect_status")]
+        status: u32,
+    },
+}
+
+fn default_redirect_status() -> u32 {
+    302
+}
+
+/// A fully rendered local reply, kept separate from the host call for testing.
+#[derive(Debug, Clone, PartialEq)]
+struct BlockResponse {
+    status: u32,
+    headers: Vec<(String, String)>,
+    body: Option<Vec<u8>>,
+}
+
+/// Fixed-window limiter. Window state lives in shared data so every worker
+/// thread sees the same counts.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct RateLimit {
+    requests_per_window: u64,
+    window_secs: u64,
+}
+
+/// The local reply for rate-limited requests. It is configured apart from
+/// `block_status`, `block_action` and `block_body_json`, so a throttled
+/// client is never told its request violated policy, or the reverse.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct RateLimitResponse {
+    #[serde(default = "default_rate_limit_status")]
+    status: u32,
+    #[serde(default)]
+    body: Option<String>,               // Default: the block reason, "Too Many Requests"
+    #[serde(default)]
+    headers: BTreeMap<String, String>,  // Sent after retry-after and the defense header
+}
+
+impl Default for RateLimitResponse {
+    fn default() -> Self {
+        RateLimitResponse { status: default_rate_limit_status(), body: None, headers: BTreeMap::new() }
+    }
+}
+
+fn default_rate_limit_status() -> u32 {
+    429
+}
+
+/// Canary probe: a request carrying `header: value` is answered by the filter
+/// with the active policy's status and rule counts, bypassing every rule.
+/// Anyone who can send the header can read the counts, so pick a value that
+/// is not guessable, or strip the header at the edge.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct SelfTest {
+    header: String,
+    #[serde(default = "default_self_test_value")]
+    value: String,
+}
+
+fn default_self_test_value() -> String {
+    "1".to_string()
+}
+
+/// Ring buffer of recent block summaries, kept in shared data as a JSON
+/// array so every worker appends to and serves the same list. The route is
+/// answered by the filter itself, behind only the rate limit and
+/// `required_headers`; restrict who can reach it at the listener.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct RecentBlocks {
+    capacity: usize,
+    route: String,
+}
+
+/// Shared-data key holding the `recent_blocks` ring buffer.
+const RECENT_BLOCKS_KEY: &str = "leukocyte_recent_blocks";
+
+impl RecentBlocks {
+    /// Appends `entry` to the stored buffer, dropping the oldest entries
+    /// beyond `capacity`. An unreadable buffer starts over.
+    fn record(&self, stored: Option<&[u8]>, entry: serde_json::Value) -> Vec<u8> {
+        let mut entries: Vec<serde_json::Value> = stored
+            .and_then(|bytes| serde_json::from_slice(bytes).ok())
+            .unwrap_or_default();
+        entries.push(entry);
+        let excess = entries.len().saturating_sub(self.capacity);
+        entries.drain(..excess);
+        serde_json::Value::Array(entries).to_string().into_bytes()
+    }
+}
+
+/// Shared-data record for one client: `window_start` (unix secs) and `count`,
+/// encoded as two little-endian u64s.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct WindowState {
+    window_start: u64,
+    count: u64,
+}
+
+impl WindowState {
+    fn encode(&self) -> [u8; 16] {
+        let mut out = [0; 16];
+        out[..8].copy_from_slice(&self.window_start.to_le_bytes());
+        out[8..].copy_from_slice(&self.count.to_le_bytes());
+        out
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let window_start = u64::from_le_bytes(bytes.get(..8)?.try_into().ok()?);
+        let count = u64::from_le_bytes(bytes.get(8..16)?.try_into().ok()?);
+        Some(WindowState { window_start, count })
+    }
+}
+
+impl RateLimit {
+    /// Counts one request at `now`. Returns the state to store and, when over
+    /// the limit, the whole seconds until the window refills (at least 1).
+    fn admit(&self, state: Option<WindowState>, now: u64) -> (WindowState, Option<u64>) {
+        let window = self.window_secs.max(1);
+        let mut state = match state {
+            Some(s) if now < s.window_start + window => s,
+            _ => WindowState { window_start: now, count: 0 },
+        };
+        state.count += 1;
+        if state.count > self.requests_per_window {
+            (state, Some((state.window_start + window - now).max(1)))
+        } else {
+            (state, None)
+        }
+    }
+
+    /// Counts the request through a compare-and-swap on shared data, retrying
+    /// a few times under contention. A read or write error is resolved by
+    /// `failure`; sustained contention lets the request through.
+    fn check(
+        &self,
+        client: &str,
+        now: u64,
+        failure: SharedDataFailure,
+        mut get: impl FnMut() -> Result<(Option<Vec<u8>>, Option<u32>), Status>,
+        mut set: impl FnMut(&[u8], Option<u32>) -> Result<(), Status>,
+    ) -> Result<(), Block> {
+        for _ in 0..3 {
+            let Ok((data, cas)) = get() else { return failure.resolve("rate limit") };
+            let (next, retry_after) = self.admit(data.as_deref().and_then(WindowState::decode), now);
+            match set(&next.encode(), cas) {
+                Ok(()) => return retry_after.map_or(Ok(()), |secs| Err(Block::rate_limited(secs, client))),
+                Err(Status::CasMismatch) => continue,
+                Err(_) => return failure.resolve("rate limit"),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fallback when `send_http_response` fails, so a request is never left
+/// paused with no reply. `Reset` closes the stream; `Continue` lets the
+/// request through to the upstream.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+enum SendFailureAction {
+    #[default]
+    Reset,
+    Continue,
+}
+
+/// Whether a gate backed by shared data lets requests through when the host
+/// cannot read or write it.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+enum SharedDataFailure {
+    #[default]
+    FailOpen,
+    FailClosed,
+}
+
+impl SharedDataFailure {
+    fn resolve(self, feature: &str) -> Result<(), Block> {
+        match self {
+            SharedDataFailure::FailOpen => Ok(()),
+            SharedDataFailure::FailClosed => Err(Block::new("Service Unavailable: Policy State Unavailable", "shared-data-unavailable",
+                format!("⚠️ [Leukocyte] Shared data unavailable for {}; failing closed", feature))),
+        }
+    }
+}
+
+/// What to do with a body that is not valid UTF-8 JSON while body policies
+/// are configured, instead of silently letting it through.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum UnparseableAction {
+    Allow,
+    Block,
+}
+
+/// Explicit handling of a body that fails JSON parsing. `InspectRaw` runs
+/// `body_raw_deny_regexes` over the whole body, not just the pre-parse window.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum NonJsonAction {
+    Allow,
+    Block,
+    InspectRaw,
+}
+
+/// `content-encoding` values the filter can decode and re-encode: request
+/// bodies for inspection, response bodies for redaction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ContentEncoding {
+    Identity,
+    Gzip,
+}
+
+impl ContentEncoding {
+    fn parse(content_encoding: Option<&str>) -> Option<Self> {
+        match content_encoding.map(|v| v.trim().to_lowercase()).as_deref() {
+            None | Some("") | Some("identity") => Some(ContentEncoding::Identity),
+            Some("gzip") | Some("x-gzip") => Some(ContentEncoding::Gzip),
+            _ => None,
+        }
+    }
+}
+
+/// How the strict allowlist treats a request whose body turns out empty.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+enum EmptyBodyAction {
+    #[default]
+    Allow,
+    Deny,
+}
+
+/// Body formats the filter knows how to inspect, chosen from the content-type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BodyKind {
+    Json,
+    /// `application/x-www-form-urlencoded`, normalized to a flat document.
+    Form,
+    Csv,
+    Multipart,
+    /// Base64 gRPC-Web frames; only trailer-frame metadata is inspected.
+    GrpcWebText,
+    /// Any other declared type, buffered only to enforce `enforce_content_type`.
+    Opaque,
+}
+
+impl BodyKind {
+    /// Whether an incomplete body can be scanned for JSON keys. Other formats
+    /// have no keys to scan, and a CSV header or form field could pass for one.
+    fn has_json_keys(self) -> bool {
+        self == BodyKind::Json
+    }
+}
+
+/// A rejection decision: the body text, the `x-leukocyte-defense` tag, and the
+/// log line describing the match (emitted once, with request context, on send).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    reason: &'static str,
+    defense: &'static str,
+    detail: String,
+    rule: Option<String>, // The matched path or header, disclosed only with `reveal_match`
+    value: Option<SensitiveValue>, // The offending value; only `block_log_line` may render it
+    severity: Option<Severity>,
+    retry_after: Option<u64>, // Set for rate-limit blocks: answered with 429 + retry-after
+}
+
+/// A raw request value held for logging. `Debug` never shows it, so a stray
+/// `{:?}` of a `Block` cannot leak it either.
+#[derive(Clone, PartialEq)]
+struct SensitiveValue(Box<str>);
+
+impl std::fmt::Debug for SensitiveValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+impl Block {
+    pub fn reason(&self) -> &'static str {
+        self.reason
+    }
+
+    /// The `x-leukocyte-defense` tag naming the check that fired.
+    pub fn defense(&self) -> &'static str {
+        self.defense
+    }
+
+    /// The log line for the match. Never carries the offending value.
+    pub fn detail(&self) -> &str {
+        &self.detail
+    }
+
+    /// `detail` names paths and rules only; values go through `with_value`.
+    fn new(reason: &'static str, defense: &'static str, detail: String) -> Self {
+        Block { reason, defense, detail, rule: None, value: None, severity: None, retry_after: None }
+    }
+
+    fn with_value(mut self, value: impl Into<String>) -> Self {
+        self.value = Some(SensitiveValue(value.into().into_boxed_str()));
+        self
+    }
+
+    fn rate_limited(retry_after: u64, client: &str) -> Self {
+        Block {
+            retry_after: Some(retry_after),
+            ..Block::new("Too Many Requests", "rate-limited",
+                format!("⚠️ [Leukocyte] Rate limit exceeded for {} (retry in {}s)", client, retry_after))
+        }
+    }
+
+    fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = Some(severity);
+        self
+    }
+
+    fn with_rule(mut self, rule: impl Into<String>) -> Self {
+        self.rule = Some(rule.into());
+        self
+    }
+}
+
+/// Who sent the request being inspected, captured once in the header phase.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct RequestInfo {
+    source: Option<String>,
+    client: Option<String>, // Client IP: the peer, or from x-forwarded-for behind a trusted proxy
+    method: Option<String>,
+    path: Option<String>,   // Raw :path, including the query string
+    route: String,          // Path component used for route matching (see `route_for`)
+    host: Option<String>,
+    origin: Option<String>,
+    request_id: Option<String>, // x-request-id, for correlating with the access log
+}
+
+impl PolicyConfig {
+    /// Parses, normalizes and compiles a policy document. Shared by
+    /// `on_configure` and pushed updates so both load identically.
+    fn load(bytes: &[u8]) -> Result<Self, String> {
+        let document = match bytes.strip_prefix(BINARY_POLICY_MAGIC) {
+            Some(encoded) => decode_binary_policy(encoded)?,
+            None => {
+                let config_str = std::str::from_utf8(bytes).map_err(|e| e.to_string())?;
+                serde_json::from_str(config_str).map_err(|e| e.to_string())?
+            }
+        };
+        let config = Self::materialize(document)?;
+
+        info!("{}", config.log_text(&format!("🧬 [Leukocyte] Configuration Transduced: {} suppression paths, {} allow paths, {} sub-policies",
+              config.suppression_paths.len(), config.allow_paths.len(), config.resolved_sub_policies.len())));
+        Ok(config)
+    }
+
+    /// Builds one fully resolved policy from its JSON document, including
+    /// each sub-policy merged over `defaults`.
+    fn materialize(document: serde_json::Value) -> Result<Self, String> {
+        let mut config: PolicyConfig = serde_json::from_value(document).map_err(|e| e.to_string())?;
+
+        config.body_hash_denylist = config.body_hash_denylist.into_iter()
+            .map(|s| s.trim().to_lowercase())
+            .collect();
+        config.sni_allow = config.sni_allow.into_iter()
+            .map(|s| s.to_lowercase())
+            .collect();
+        config.sni_deny = config.sni_deny.into_iter()
+            .map(|s| s.to_lowercase())
+            .collect();
+        config.allowed_hosts = config.allowed_hosts.into_iter()
+            .map(|s| s.to_lowercase())
+            .collect();
+        config.suppression_path_segments = config.suppression_path_segments.into_iter()
+            .map(|s| s.to_lowercase())
+            .collect();
+        if let Some(probe) = &mut config.self_test {
+            probe.header = probe.header.to_lowercase();
+        }
+        config.allowed_origins = config.allowed_origins.into_iter()
+            .map(|s| s.trim_end_matches('/').to_lowercase())
+            .collect();
+        config.deny_key_names = config.deny_key_names.into_iter()
+            .map(|s| s.to_lowercase())
+            .collect();
+        config.required_headers = config.required_headers.into_iter()
+            .map(|s| s.to_lowercase())
+            .collect();
+        config.strip_headers = config.strip_headers.into_iter()
+            .map(|s| s.to_lowercase())
+            .collect();
+        config.allowed_protocols = config.allowed_protocols.into_iter()
+            .map(|s| s.to_uppercase())
+            .collect();
+        // Auth schemes are case-insensitive (RFC 7235)
+        config.allowed_auth_schemes = config.allowed_auth_schemes.into_iter()
+            .map(|s| s.to_lowercase())
+            .collect();
+
+        // `paranoid` is a bundle, not a check of its own: it turns on
+        // require_json_object, reject_duplicate_keys, reject_unsafe_numbers
+        // and reject_control_chars, and blocks unparseable bodies whatever
+        // non_json_action and on_unparseable say.
+        if config.paranoid {
+            config.require_json_object = true;
+            config.reject_duplicate_keys = true;
+            config.reject_unsafe_numbers = true;
+            config.reject_control_chars = true;
+            config.non_json_action = Some(NonJsonAction::Block);
+        }
+
+        config.compile_errors = config.compile();
+        for error in &config.compile_errors {
+            warn!("{}", config.log_text(&format!("⚠️ [Leukocyte] Skipping invalid rule: {}", error)));
+        }
+
+        config.resolve_sub_policies()?;
+        Ok(config)
+    }
+
+    /// Field-level merge: a key present in a sub-policy replaces the same key
+    /// from `defaults` wholesale (sets are not unioned). Sub-policies nest one
+    /// level only.
+    fn resolve_sub_policies(&mut self) -> Result<(), String> {
+        let mut resolved = Vec::new();
+        for (i, raw) in self.sub_policies.iter().enumerate() {
+            let matcher: PolicyMatch = match raw.get("match") {
+                Some(m) => serde_json::from_value(m.clone()).map_err(|e| format!("sub_policies[{}].match: {}", i, e))?,
+                None => PolicyMatch::default(),
+            };
+
+            let mut merged = self.defaults.clone();
+            merged.extend(raw.iter().filter(|(k, _)| *k != "match").map(|(k, v)| (k.clone(), v.clone())));
+            merged.remove("defaults");
+            merged.remove("sub_policies");
+
+            let policy = Self::materialize(serde_json::Value::Object(merged))
+                .map_err(|e| format!("sub_policies[{}]: {}", i, e))?;
+            self.compile_errors.extend(policy.compile_errors.iter().map(|e| format!("sub_policies[{}]: {}", i, e)));
+            resolved.push(SubPolicy { matcher, policy: Rc::new(policy) });
+        }
+        self.resolved_sub_policies = resolved;
+        Ok(())
+    }
+
+    /// The client IP for `source`. Only a peer inside `trusted_proxies` may
+    /// speak for another hop: `forwarded_for` is then walked right to left and
+    /// the first untrusted entry wins, so a client cannot spoof itself by
+    /// prepending entries. An unparseable entry stops the walk.
+    fn client_address(&self, source: &str, forwarded_for: Option<&str>) -> String {
+        let peer = client_ip(source);
+        let Ok(mut client) = peer.parse::<std::net::IpAddr>() else { return peer.to_string() };
+        let trusted = |ip: &std::net::IpAddr| self.trusted_nets.iter().any(|net| net.contains(ip));
+
+        if trusted(&client) {
+            for entry in forwarded_for.unwrap_or("").rsplit(',') {
+                let Ok(hop) = client_ip(entry.trim()).parse::<std::net::IpAddr>() else { break };
+                client = hop;
+                if !trusted(&hop) {
+                    break;
+                }
+            }
+        }
+        client.to_string()
+    }
+
+    /// Reads the request attributes through the configured header names.
+    fn request_info(&self, source: Option<String>, header: impl Fn(&str) -> Option<String>) -> RequestInfo {
+        let names = &self.header_names;
+        let path = header(&names.path);
+        RequestInfo {
+            source,
+            method: header(&names.method),
+            route: self.route_for(path.as_deref().unwrap_or("/")),
+            path,
+            host: header(&names.host),
+            origin: header("origin"),
+            request_id: header("x-request-id"),
+            ..Default::default()
+        }
+    }
+
+    /// The first sub-policy whose `match` fits the request, if any.
+    fn sub_policy_for(&self, request: &RequestInfo) -> Option<Rc<PolicyConfig>> {
+        self.resolved_sub_policies.iter()
+            .find(|sub| sub.matcher.matches(request))
+            .map(|sub| Rc::clone(&sub.policy))
+    }
+
+    /// Compiles pattern-based rules. Invalid patterns are skipped and returned
+    /// as error messages so the remaining rules still apply.
+    fn compile(&mut self) -> Vec<String> {
+        let mut errors = Vec::new();
+        self.value_deny = compile_patterns(&self.value_deny_regexes, &mut errors);
+        self.header_value_deny = self.header_value_deny_regexes.iter()
+            .map(|(name, patterns)| (name.to_lowercase(), compile_patterns(patterns, &mut errors)))
+            .collect();
+        self.body_raw_deny = compile_patterns(&self.body_raw_deny_regexes, &mut errors);
+        // Anchored: a value conforms only if the pattern covers all of it
+        self.value_allow = self.value_allow_regex.as_ref()
+            .and_then(|p| Regex::new(&format!("^(?:{})$", p)).map_err(|e| errors.push(format!("{}: {}", p, e))).ok());
+        // Anchored so a pattern names whole headers, like the exact set does
+        self.header_deny = self.suppression_header_regexes.iter()
+            .filter_map(|p| Regex::new(&format!("^(?:{})$", p)).map_err(|e| errors.push(format!("{}: {}", p, e))).ok())
+            .collect();
+        self.glob_rules = self.suppression_globs.iter().map(|g| (g, false))
+            .chain(self.suppression_glob_exceptions.iter().map(|g| (g, true)))
+            .filter_map(|(g, exception)| GlobRule::new(g, exception).map_err(|e| errors.push(format!("{}: {}", g, e))).ok())
+            .collect();
+        self.allowed_routes = self.allowed_route_patterns.iter()
+            .filter_map(|g| glob_to_regex(g).map_err(|e| errors.push(format!("{}: {}", g, e))).ok())
+            .collect();
+        self.allowed_globs = self.allow_globs.iter()
+            .filter_map(|g| path_glob_to_regex(g).map_err(|e| errors.push(format!("{}: {}", g, e))).ok())
+            .collect();
+        self.trusted_nets = self.trusted_proxies.iter()
+            .filter_map(|c| Cidr::parse(c).ok_or_else(|| errors.push(format!("{}: invalid CIDR", c))).ok())
+            .collect();
+
+        // Normalize to lowercase for header matching (Envoy uses lowercase headers)
+        for entry in self.suppression_entries.iter().filter(|e| e.enabled()) {
+            match entry {
+                SuppressionEntry::Path(path) => {
+                    self.suppression_paths.insert(path.to_lowercase());
+                }
+                SuppressionEntry::Valued { path, value, .. } => {
+                    self.suppression_values.entry(path.to_lowercase()).or_default().push(value.clone());
+                }
+                SuppressionEntry::AtDepth { path, depth, .. } => {
+                    self.suppression_depths.push((path.to_lowercase(), *depth));
+                }
+                SuppressionEntry::Typed { path, expected_type: ValueType::Any, .. } => {
+                    self.suppression_paths.insert(path.to_lowercase());
+                }
+                SuppressionEntry::Typed { path, expected_type, .. } => {
+                    self.suppression_types.insert(path.to_lowercase(), *expected_type);
+                }
+            }
+        }
+
+        // Normalize to lowercase for path matching, like the other path sets
+        for entry in self.allow_entries.iter().filter(|e| e.enabled()) {
+            let path = match entry {
+                AllowEntry::Path(path) => path.to_lowercase(),
+                AllowEntry::Constrained(AllowConstraint { path, values, array, expected_type, .. }) => {
+                    let path = path.to_lowercase();
+                    if *expected_type != ValueType::Any {
+                        self.allow_types.insert(path.clone(), *expected_type);
+                    }
+                    if let Some(values) = values {
+                        self.allow_values.insert(path.clone(), values.iter().cloned().collect());
+                    }
+                    if *array {
+                        self.allow_arrays.insert(path.clone());
+                    }
+                    path
+                }
+            };
+            self.allow_paths.insert(path);
+        }
+        // Required paths are the mandatory part of the allowlist; `allow_paths` is the optional part
+        self.required_paths = self.required_paths.iter().map(|p| p.to_lowercase()).collect();
+        self.allow_paths.extend(self.required_paths.iter().cloned());
+        errors
+    }
+
+    fn render_block(&self, block: &Block, request: &RequestInfo, is_grpc: bool) -> BlockResponse {
+        // Indistinguishable from a missing endpoint: no defense header, no body.
+        // Logs, metrics and incident records still carry the real decision.
+        let hidden = self.block_as_not_found
+            && matches!(block.defense, "methylated" | "methylated-header" | "path-segment-denied" | "route-not-allowed");
+        if hidden {
+            let headers = if is_grpc {
+                vec![
+                    ("content-type".into(), "application/grpc".into()),
+                    ("grpc-status".into(), "5".into()), // NOT_FOUND
+                ]
+            } else {
+                Vec::new()
+            };
+            return BlockResponse { status: if is_grpc { 200 } else { 404 }, headers, body: None };
+        }
+
+        if is_grpc {
+            // gRPC clients cannot follow redirects or read a 403; answer with a
+            // Trailers-Only response (HTTP 200 carrying grpc-status/grpc-message).
+            let grpc_status = match block.retry_after {
+                Some(_) => 8, // RESOURCE_EXHAUSTED
+                None => self.grpc_block_status,
+            };
+            return BlockResponse {
+                status: 200,
+                headers: vec![
+                    ("content-type".into(), "application/grpc".into()),
+                    ("x-leukocyte-defense".into(), block.defense.into()),
+                    ("grpc-status".into(), grpc_status.to_string()),
+                    ("grpc-message".into(), block.reason.into()),
+                ],
+                body: None,
+            };
+        }
+
+        if let Some(retry_after) = block.retry_after {
+            let reply = &self.rate_limit_response;
+            let mut headers = vec![
+                ("retry-after".into(), retry_after.to_string()),
+                ("x-leukocyte-defense".into(), block.defense.into()),
+            ];
+            headers.extend(reply.headers.iter().map(|(name, value)| (name.to_lowercase(), value.clone())));
+            return BlockResponse {
+                status: reply.status,
+                headers,
+                body: Some(reply.body.as_deref().unwrap_or(block.reason).as_bytes().to_vec()),
+            };
+        }
+
+        let mut response = match &self.block_action {
+            BlockAction::Deny if self.block_body_json => {
+                let mut envelope = serde_json::json!({
+                    "error": "access_denied",
+                    "defense": block.defense,
+                });
+                if let (true, Some(rule)) = (self.reveal_match, &block.rule) {
+                    envelope["rule"] = self.report(rule).into();
+                }
+                BlockResponse {
+                    status: self.block_status,
+                    headers: vec![
+                        ("content-type".into(), "application/json".into()),
+                        ("x-leukocyte-defense".into(), block.defense.into()),
+                    ],
+                    body: Some(envelope.to_string().into_bytes()),
+                }
+            }
+            BlockAction::Deny => {
+                let body = match (self.reveal_match, &block.rule) {
+                    (true, Some(rule)) => format!("{} ({})", block.reason, self.report(rule)),
+                    _ => block.reason.to_string(),
+                };
+                BlockResponse {
+                    status: self.block_status,
+                    headers: vec![("x-leukocyte-defense".into(), block.defense.into())],
+                    body: Some(body.into_bytes()),
+                }
+            }
+            BlockAction::Redirect { location, status } => BlockResponse {
+                status: if (300..400).contains(status) { *status } else { default_redirect_status() },
+                headers: vec![
+                    ("location".into(), location.clone()),
+                    ("x-leukocyte-defense".into(), block.defense.into()),
+                ],
+                body: None,
+            },
+        };
+
+        if let Some(cors) = &self.block_cors {
+            let origin = match (cors.allow_origin.as_str(), &request.origin) {
+                ("*", Some(origin)) => {
+                    response.headers.push(("vary".into(), "origin".into()));
+                    origin.clone()
+                }
+                (allowed, _) => allowed.to_string(),
+            };
+            response.headers.push(("access-control-allow-origin".into(), origin));
+            if let Some(methods) = &cors.allow_methods {
+                response.headers.push(("access-control-allow-methods".into(), methods.clone()));
+            }
+            if let Some(headers) = &cors.allow_headers {
+                response.headers.push(("access-control-allow-headers".into(), headers.clone()));
+            }
+        }
+        response
+    }
+
+    /// JSON incident record enqueued for the control plane on every block.
+    fn incident_record(&self, block: &Block, request: &RequestInfo, timestamp: u64) -> Vec<u8> {
+        let mut record = serde_json::json!({
+            "timestamp": timestamp,
+            "defense": block.defense,
+            "source": request.source,
+            "method": request.method,
+        });
+        if let Some(severity) = block.severity {
+            record["severity"] = format!("{:?}", severity).to_lowercase().into();
+        }
+        if self.reveal_match {
+            record["path"] = self.report(&request.route).into();
+            record["rule"] = block.rule.as_deref().map(|rule| self.report(rule)).into();
+        }
+        record.to_string().into_bytes()
+    }
+
+    /// One `recent_blocks` entry. Like incident records, the route is only
+    /// included under `reveal_match`.
+    fn recent_block_entry(&self, block: &Block, request: &RequestInfo, timestamp: u64) -> serde_json::Value {
+        let path = if self.reveal_match { self.report(&request.route) } else { "<redacted>".into() };
+        serde_json::json!({
+            "timestamp": timestamp,
+            "defense": block.defense,
+            "path": path,
         })
-    });
-}}
+    }
+
+    /// The structured line logged for every block, and the only place a
+    /// block's value is rendered: redacted, or masked under `log_value_snippet`.
+    /// The request path is only included when `reveal_match` is on.
+    fn block_log_line(&self, block: &Block, request: &RequestInfo) -> String {
+        let mut line = format!("{} | defense={} source={} method={}",
+            self.report(self.log_text(&block.detail)),
+            block.defense,
+            request.source.as_deref().unwrap_or("unknown"),
+            request.method.as_deref().unwrap_or("-"));
+        if let Some(SensitiveValue(value)) = &block.value {
+            line.push_str(&format!(" value={}", self.value_for_log(value)));
+        }
+        if self.reveal_match {
+            line.push_str(&format!(" path={}", self.report(request.path.as_deref().unwrap_or("-"))));
+        }
+        line
+    }
+
+    /// `text` cut to `max_report_bytes` on a character boundary, with `...`
+    /// marking the cut (ASCII, so `plain_logs` lines stay ASCII). Everything a
+    /// block echoes back goes through it.
+    fn report<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if text.len() <= self.max_report_bytes {
+            return Cow::Borrowed(text);
+        }
+        let mut end = self.max_report_bytes;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        Cow::Owned(format!("{}...", &text[..end]))
+    }
+
+    /// Log text with its emoji prefix removed when `plain_logs` is set; the
+    /// bracketed tag (`[Leukocyte]`, `[Immunity]`, ...) stays.
+    fn log_text<'a>(&self, text: &'a str) -> &'a str {
+        if self.plain_logs {
+            text.trim_start_matches(|c: char| !c.is_ascii() || c.is_whitespace())
+        } else {
+            text
+        }
+    }
+
+    /// How a matched value may appear in logs: masked and truncated, or not at all.
+    fn value_for_log(&self, value: &str) -> String {
+        if self.log_value_snippet.enabled {
+            self.report(&mask_snippet(value, self.log_value_snippet.max_len)).into_owned()
+        } else {
+            "<redacted>".to_string()
+        }
+    }
+
+    /// The header-phase action once headers pass inspection. In `Pause` mode a
+    /// pending body keeps them held; the body callback returning `Continue`
+    /// at its verdict resumes headers and body together.
+    ///
+    /// A client sending `Expect: 100-continue` holds its body until it sees
+    /// the interim response, which comes from upstream once it has the
+    /// headers (or from the proxy, which also waits for them to pass the
+    /// filter chain). Holding the headers for a body that cannot arrive would
+    /// deadlock the upload, so they are released and, as in `Stream` mode, the
+    /// body is still buffered and judged before any of it goes upstream.
+    fn headers_action(&self, body_pending: bool, expects_continue: bool) -> Action {
+        match self.buffering_mode {
+            BufferingMode::Pause if body_pending && !expects_continue => Action::Pause,
+            _ => Action::Continue,
+        }
+    }
+
+    /// `None` means the body is never buffered. Headers-only mode also skips
+    /// allowlist enforcement, since `allow_paths` only applies to bodies.
+    fn body_kind(&self, content_type: Option<&str>) -> Option<BodyKind> {
+        if !self.inspect_body {
+            return None;
+        }
+        match content_type {
+            Some(ct) if is_grpc_content_type(ct) => None,
+            // Framed protobuf, like gRPC; only grpc-web-text trailer metadata is ever decoded
+            Some(ct) if is_grpc_web_content_type(ct) => {
+                (self.grpc_web_text_scan && is_grpc_web_text(ct)).then_some(BodyKind::GrpcWebText)
+            }
+            Some(ct) if is_json_content_type(ct) => Some(BodyKind::Json),
+            Some(ct) if is_form_content_type(ct) => Some(BodyKind::Form),
+            Some(ct) if is_csv_content_type(ct) => Some(BodyKind::Csv),
+            Some(ct) if is_multipart_content_type(ct) => Some(BodyKind::Multipart),
+            Some(_) if self.enforce_content_type => Some(BodyKind::Opaque),
+            Some(_) => None,
+            // Bodies without a declared content-type are still attempted as JSON (legacy behavior)
+            None => Some(BodyKind::Json),
+        }
+    }
+
+    /// Whether a body skips inspection given a uniform `roll` in [0, 1). Strict
+    /// allowlists always inspect, since sampling them out would admit anything.
+    fn samples_out(&self, roll: f64) -> bool {
+        !self.has_allowlist() && roll >= self.body_inspection_sample
+    }
+
+    /// Whether bodies run under a strict allowlist, of exact paths or globs.
+    fn has_allowlist(&self) -> bool {
+        !self.allow_paths.is_empty() || !self.allowed_globs.is_empty()
+    }
+
+    /// Bypassed routes (health checks etc.) skip all inspection.
+    fn is_bypassed(&self, route: &str) -> bool {
+        self.bypass_paths.iter().any(|p| match p.strip_suffix('*') {
+            Some(prefix) => route.starts_with(prefix),
+            None => route == p,
+        })
+    }
+
+    fn is_preflight_passthrough(&self, method: Option<&str>) -> bool {
+        self.passthrough_options && method.is_some_and(|m| m.eq_ignore_ascii_case("OPTIONS"))
+    }
+
+    /// Why the header phase lets a request skip body inspection, or `None`
+    /// when its body will be inspected (or it has none). `roll` is drawn
+    /// only for a body that would otherwise be inspected.
+    fn passthrough_for(&self, request: &RequestInfo, headers: &[(String, String)], body_kind: Option<BodyKind>,
+                       end_of_stream: bool, roll: impl FnOnce() -> f64) -> Option<Passthrough> {
+        if self.is_bypassed(&request.route) {
+            Some(Passthrough::HealthCheck)
+        } else if self.is_preflight_passthrough(request.method.as_deref()) {
+            Some(Passthrough::Preflight)
+        } else if is_websocket_upgrade(headers) {
+            Some(Passthrough::WebSocket)
+        } else if body_kind.is_none() {
+            (!end_of_stream).then_some(Passthrough::ContentType)
+        } else if self.samples_out(roll()) {
+            Some(Passthrough::SampledOut)
+        } else {
+            None
+        }
+    }
+
+    fn inspect_headers(&self, headers: &[(String, String)]) -> Result<(), Block> {
+        if !self.inspect_headers {
+            return Ok(());
+        }
+
+        for (name, _value) in headers {
+            let normalized = self.normalize_header_name(name);
+            if self.suppression_paths.contains(normalized.as_ref()) {
+                return Err(Block::new("Access Denied: Pathogen Header Suppressed", "methylated-header",
+                    format!("🛡️ [Methylation] Suppressed expression of pathogen header: {}", name))
+                    .with_rule(normalized));
+            }
+            if let Some(pattern) = self.header_deny.iter().find(|re| re.is_match(&normalized)) {
+                return Err(Block::new("Access Denied: Pathogen Header Suppressed", "methylated-header",
+                    format!("🛡️ [Methylation] Header {} matched suppression pattern {}", name, pattern))
+                    .with_rule(pattern.as_str()));
+            }
+        }
+
+        if !self.header_value_deny.is_empty() {
+            for (name, values) in group_headers(headers) {
+                let Some(patterns) = self.header_value_deny.get(name.as_str()) else { continue };
+                // Each occurrence on its own, then (opt-in) the field a recipient folds them into,
+                // so a pattern split across two occurrences still matches
+                let joined = (self.match_joined_header_values && values.len() > 1)
+                    .then(|| values.join(if name == "cookie" { "; " } else { ", " }));
+                for value in values.iter().copied().chain(joined.as_deref()) {
+                    if let Some(pattern) = patterns.iter().find(|re| re.is_match(value)) {
+                        return Err(Block::new("Access Denied: Denied Header Value", "header-value-denied",
+                            format!("🛡️ [Methylation] Header {} value matched deny pattern {}", name, pattern))
+                            .with_rule(name.as_str())
+                            .with_value(value));
+                    }
+                }
+            }
+        }
+
+        if !self.allowed_auth_schemes.is_empty() {
+            // Every occurrence counts: a second authorization header must not slip a scheme past.
+            // A missing header is not a structural violation; presence is a separate concern.
+            let authorizations = headers.iter()
+                .filter(|(k, _)| k.eq_ignore_ascii_case("authorization"))
+                .map(|(_, v)| v.as_str());
+            for value in authorizations {
+                match auth_scheme(value) {
+                    Some(scheme) if self.allowed_auth_schemes.contains(&scheme) => {}
+                    scheme => {
+                        return Err(Block::new("Access Denied: Unsupported Authorization Scheme", "bad-auth-scheme",
+                            format!("🛡️ [Immunity] Rejected authorization scheme: {:?}", scheme)));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lowercases a header name once, to compare against the lowercased rule
+    /// sets. Envoy already lowercases, so the common case borrows.
+    fn normalize_header_name<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        if self.normalize_header_names && !name.is_ascii() {
+            // Non-conformant proxies may forward names such as fullwidth
+            // letters that only match a suppressed header after NFKC.
+            Cow::Owned(name.nfkc().collect::<String>().to_lowercase())
+        } else if name.bytes().any(|b| b.is_ascii_uppercase()) || !name.is_ascii() {
+            Cow::Owned(name.to_lowercase())
+        } else {
+            Cow::Borrowed(name)
+        }
+    }
+
+    /// Names of request headers to remove before forwarding. An inspected
+    /// body that may be rewritten also loses its now-unreliable `content-length`.
+    fn headers_to_strip<'a>(&self, headers: &'a [(String, String)], inspect_body: bool) -> Vec<&'a str> {
+        let rewrites = inspect_body && self.rewrites_body();
+        headers.iter()
+            .map(|(name, _)| name.as_str())
+            .filter(|name| {
+                let name = self.normalize_header_name(name);
+                self.strip_headers.contains(name.as_ref()) || (rewrites && name == "content-length")
+            })
+            .collect()
+    }
+
+    /// Whether a body verdict can re-encode the body: suppressed paths are
+    /// stripped under `violation_threshold` and foreign ones under
+    /// `scrub_unallowed`, and the re-encode covers form and gzip bodies too.
+    fn rewrites_body(&self) -> bool {
+        self.violation_threshold > 0 || (self.scrub_unallowed && self.has_allowlist())
+    }
+
+    /// Request-only counterpart of `inspect_headers` (trailers never carry
+    /// these): every `required_headers` entry must be present.
+    fn check_required_headers(&self, headers: &[(String, String)]) -> Result<(), Block> {
+        if !self.inspect_headers {
+            return Ok(());
+        }
+        let mut missing: Vec<&String> = self.required_headers.iter()
+            .filter(|required| !headers.iter().any(|(name, _)| name.eq_ignore_ascii_case(required)))
+            .collect();
+        missing.sort();
+        match missing.first() {
+            Some(name) => Err(Block::new("Access Denied: Missing Required Header", "missing-header",
+                format!("🛡️ [Immunity] Required header absent: {}", name))
+                .with_rule(name.as_str())),
+            None => Ok(()),
+        }
+    }
+
+    /// Applies `property_rules` using `lookup` to resolve attribute paths. An
+    /// `allow` match stops evaluation and returns `Ok(true)`; a `deny` match blocks.
+    fn inspect_properties(&self, lookup: impl Fn(&[&str]) -> Option<String>) -> Result<bool, Block> {
+        for rule in &self.property_rules {
+            let segments: Vec<&str> = rule.path.split('.').collect();
+            let actual = lookup(&segments);
+            let matched = match &rule.condition {
+                PropertyCondition::Equals(v) => actual.as_deref() == Some(v.as_str()),
+                PropertyCondition::NotEquals(v) => actual.as_deref() != Some(v.as_str()),
+            };
+            if !matched {
+                continue;
+            }
+            return match rule.action {
+                RuleAction::Allow => Ok(true),
+                RuleAction::Deny => Err(Block::new("Access Denied: Property Rule", "property-denied",
+                    format!("🛡️ [Immunity] Property rule matched: {}", rule.path))
+                    .with_rule(rule.path.as_str())
+                    .with_value(actual.unwrap_or_default())),
+            };
+        }
+        Ok(false)
+    }
+
+    /// The first matching group decides: `deny` blocks, `allow` ends group
+    /// evaluation. Body path conditions are false when there is no body.
+    fn evaluate_groups(&self, request: &RequestInfo, body: Option<&serde_json::Value>) -> Result<(), Block> {
+        let Some(group) = self.rule_groups.iter().find(|g| g.when.holds(request, body)) else {
+            return Ok(());
+        };
+        match group.action {
+            RuleAction::Allow => Ok(()),
+            RuleAction::Deny => Err(Block::new("Access Denied: Rule Group", "rule-group",
+                format!("🛡️ [Immunity] Rule group '{}' matched (severity {:?})", group.name, group.severity))
+                .with_rule(group.name.as_str())
+                .with_severity(group.severity)),
+        }
+    }
+
+    /// Deny-by-default posture. A request passes only if an explicit allow
+    /// matched: an `allow` property rule, or a body that will be checked
+    /// against a non-empty `allow_paths` (which then decides on its own).
+    /// Body-less requests can therefore only be admitted by property rules.
+    fn check_default_action(&self, explicitly_allowed: bool, body_allowlisted: bool) -> Result<(), Block> {
+        match self.default_action {
+            RuleAction::Deny if !explicitly_allowed && !body_allowlisted => {
+                Err(Block::new("Access Denied: Not Explicitly Allowed", "default-deny",
+                    "🛡️ [Immunity] No allow rule matched (default deny)".to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// TLS gating from `connection.tls_version` and the requested SNI. An absent
+    /// or empty version means a plaintext connection, governed by `allow_plaintext`.
+    fn inspect_tls(&self, tls_version: Option<&str>, sni: Option<&str>) -> Result<(), Block> {
+        let tls_version = tls_version.filter(|v| !v.is_empty());
+        let Some(version) = tls_version else {
+            if self.allow_plaintext {
+                return Ok(());
+            }
+            return Err(Block::new("Access Denied: TLS Required", "tls-required",
+                "🛡️ [Immunity] Plaintext connection rejected".to_string()));
+        };
+
+        if let Some(min) = &self.min_tls_version {
+            // Unrecognized versions fail closed
+            if tls_version_rank(version).is_none_or(|v| Some(v) < tls_version_rank(min)) {
+                return Err(Block::new("Access Denied: TLS Version Too Old", "tls-version",
+                    format!("🛡️ [Immunity] TLS version {} below minimum {}", version, min)));
+            }
+        }
+
+        let sni = sni.unwrap_or("").to_lowercase();
+        if self.sni_deny.contains(&sni) || (!self.sni_allow.is_empty() && !self.sni_allow.contains(&sni)) {
+            return Err(Block::new("Access Denied: Server Name Not Allowed", "sni-denied",
+                format!("🛡️ [Immunity] Rejected SNI: {:?}", sni)));
+        }
+        Ok(())
+    }
+
+    /// Gates on `request.protocol`. Hosts that do not report it are let
+    /// through, since the version is unknown rather than disallowed.
+    fn inspect_protocol(&self, protocol: Option<&str>) -> Result<(), Block> {
+        let Some(protocol) = protocol.filter(|p| !p.is_empty()) else { return Ok(()) };
+        if self.allowed_protocols.is_empty() || self.allowed_protocols.contains(&protocol.to_uppercase()) {
+            return Ok(());
+        }
+        Err(Block::new("Access Denied: Protocol Not Allowed", "protocol-not-allowed",
+            format!("🛡️ [Immunity] Rejected protocol: {}", protocol)))
+    }
+
+    /// The path component that route-based policy selection matches against.
+    fn route_for(&self, uri: &str) -> String {
+        let path = uri.split_once('?').map_or(uri, |(p, _)| p);
+        if self.normalize_path {
+            normalize_path(path)
+        } else {
+            path.to_string()
+        }
+    }
+
+    /// Route gate: with `allowed_route_patterns` set, the route must match one.
+    /// Matching always uses the normalized form so dot segments cannot walk
+    /// from an allowed prefix into an unlisted route.
+    fn check_route_allowed(&self, route: &str) -> Result<(), Block> {
+        if self.allowed_route_patterns.is_empty() {
+            return Ok(());
+        }
+        let route = normalize_path(route);
+        if self.allowed_routes.iter().any(|re| re.is_match(&route)) {
+            return Ok(());
+        }
+        Err(Block::new("Access Denied: Route Not Allowed", "route-not-allowed",
+            "🛡️ [Immunity] Route matches no allowed pattern".to_string()))
+    }
+
+    /// Host gate: with `allowed_hosts` set, the request's host (port and any
+    /// trailing dot removed) must be listed, exactly or under a `*.` entry.
+    fn check_host_allowed(&self, host: Option<&str>) -> Result<(), Block> {
+        if self.allowed_hosts.is_empty() {
+            return Ok(());
+        }
+        let name = host.map(|h| host_name(h).to_lowercase());
+        let allowed = name.as_deref().is_some_and(|name| {
+            self.allowed_hosts.contains(name) || self.allowed_hosts.iter().any(|entry| {
+                entry.strip_prefix("*.").is_some_and(|domain| {
+                    name.strip_suffix(domain).is_some_and(|sub| sub.len() > 1 && sub.ends_with('.'))
+                })
+            })
+        });
+        if allowed {
+            return Ok(());
+        }
+        Err(match name {
+            Some(name) => Block::new("Access Denied: Host Not Allowed", "host-not-allowed",
+                "🛡️ [Immunity] Host not in allowed_hosts".to_string()).with_value(name),
+            None => Block::new("Access Denied: Host Not Allowed", "host-not-allowed",
+                "🛡️ [Immunity] Request carries no host".to_string()),
+        })
+    }
+
+    /// CSRF gate: with `require_origin`, a mutating request must come from an
+    /// allowed origin. `Origin` is authoritative when sent; otherwise the
+    /// origin of `Referer` is used, and a request with neither is refused.
+    /// The opaque `null` origin (sandboxed frames, some redirects) only passes
+    /// when `null` itself is listed.
+    fn check_origin(&self, method: Option<&str>, origin: Option<&str>, referer: Option<&str>) -> Result<(), Block> {
+        let safe = method.is_some_and(|m| ["GET", "HEAD", "OPTIONS", "TRACE"].iter().any(|s| s.eq_ignore_ascii_case(m)));
+        if !self.require_origin || safe {
+            return Ok(());
+        }
+        let claimed = match origin {
+            Some(origin) => Some(origin.trim().trim_end_matches('/').to_lowercase()),
+            None => referer.and_then(referer_origin),
+        };
+        let Some(claimed) = claimed else {
+            return Err(Block::new("Access Denied: Origin Required", "origin-denied",
+                "🛡️ [Immunity] Mutating request without Origin or Referer".to_string()));
+        };
+        if self.allowed_origins.contains(&claimed) {
+            return Ok(());
+        }
+        Err(Block::new("Access Denied: Origin Not Allowed", "origin-denied",
+            "🛡️ [Immunity] Origin not in allowed_origins".to_string())
+            .with_value(claimed))
+    }
+
+    /// With `merge_query_params`, the decoded query parameters as the value
+    /// body rules see under `query`; `None` without a query string.
+    ///
+    /// The params join the strict model like body paths: under an allowlist
+    /// `query` and each `query.<param>` must be allowed or they are foreign
+    /// antigens, and a `query.` entry in `required_paths` requires the param. The
+    /// `:path` is never rewritten, so `scrub_unallowed` blocks a foreign param
+    /// rather than claiming to drop it.
+    fn query_document(&self, uri: Option<&str>) -> Option<serde_json::Value> {
+        if !self.merge_query_params {
+            return None;
+        }
+        let (_, query) = uri?.split_once('?')?;
+        let query = query.split('#').next().unwrap_or(query);
+        let fields = form_fields(query);
+        (!fields.is_empty()).then_some(serde_json::Value::Object(fields))
+    }
+
+    /// Checks on the raw `:path` (path component and query parameters).
+    fn inspect_uri(&self, uri: &str) -> Result<(), Block> {
+        if let Some(limit) = self.max_uri_length {
+            let length = match self.uri_length_form {
+                UriLengthForm::Raw => uri.len(),
+                UriLengthForm::Decoded => percent_decode(uri).len(),
+            };
+            if length > limit {
+                return Err(Block::new("Access Denied: URI Too Long", "uri-too-long",
+                    format!("🛡️ [Immunity] URI too long: {} bytes (limit {})", length, limit)));
+            }
+        }
+
+        if !self.suppression_path_segments.is_empty() {
+            let path = uri.split(['?', '#']).next().unwrap_or(uri);
+            // A decoded segment may itself hold separators (`%2e%2e%2f`), so split again
+            for segment in path.split('/').map(percent_decode) {
+                let hit = segment.split(['/', '\\'])
+                    .map(str::to_lowercase)
+                    .find(|part| self.suppression_path_segments.contains(part));
+                if let Some(part) = hit {
+                    return Err(Block::new("Access Denied: Path Segment Suppressed", "path-segment-denied",
+                        format!("🛡️ [Methylation] Suppressed path segment in URI: {}", part))
+                        .with_rule(part));
+                }
+            }
+        }
+
+        if self.reject_double_encoding {
+            let (path, query) = uri.split_once('?').unwrap_or((uri, ""));
+            let components = std::iter::once(path)
+                .chain(query.split('&').flat_map(|pair| pair.splitn(2, '=')));
+            for component in components {
+                if is_double_encoded(component) {
+                    return Err(Block::new("Access Denied: Double-Encoded URI", "double-encoded",
+                        "🛡️ [Immunity] Double URL-encoding detected".to_string())
+                        .with_value(component));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `Ok(true)` keeps buffering. `Ok(false)` means the cap was hit in `Pass`
+    /// mode and the body must be forwarded without inspection.
+    fn check_buffer(&self, buffered: usize) -> Result<bool, Block> {
+        match self.buffer_cap_bytes {
+            Some(cap) if buffered > cap => match self.on_buffer_overflow {
+                OverflowAction::Block => Err(Block::new("Access Denied: Body Too Large To Inspect", "buffer-overflow",
+                    format!("⚠️ [Leukocyte] Body buffer cap exceeded: {} bytes (cap {})", buffered, cap))),
+                OverflowAction::Pass => {
+                    warn!("{}", self.log_text(&format!("⚠️ [Leukocyte] Body buffer cap exceeded, passing uninspected: {} bytes", buffered)));
+                    Ok(false)
+                }
+            },
+            _ => Ok(true),
+        }
+    }
+
+    /// Inspects only the first `inspect_prefix_bytes` of a larger body.
+    ///
+    /// Security trade-off: anything beyond the window is never checked, and the
+    /// window uses the best-effort streaming scan, so only suppressed keys are
+    /// enforced there (allowlists, value and composite rules need the full body).
+    fn inspect_prefix_window(&self, body: &[u8]) -> Result<(), Block> {
+        let limit = self.inspect_prefix_bytes.unwrap_or(body.len()).min(body.len());
+        self.inspect_partial(&body[..limit])
+    }
+
+    /// Best-effort suppression check on an incomplete body. Allowlist enforcement
+    /// needs the full document, so only suppressed keys can be caught early.
+    fn inspect_partial(&self, prefix: &[u8]) -> Result<(), Block> {
+        for param in scan_prefix_paths(prefix) {
+            if self.suppression_paths.contains(&param) {
+                return Err(Block::new("Access Denied: Pathogen Suppressed", "methylated",
+                    format!("🛡️ [Methylation] Suppressed expression of pathogen path (streaming): {}", param))
+                    .with_rule(param));
+            }
+        }
+        Ok(())
+    }
+
+    fn has_body_policies(&self) -> bool {
+        !self.suppression_paths.is_empty()
+            || !self.suppression_values.is_empty()
+            || !self.suppression_types.is_empty()
+            || !self.suppression_depths.is_empty()
+            || !self.deny_key_names.is_empty()
+            || !self.glob_rules.is_empty()
+            || self.has_allowlist()
+            || !self.array_value_deny.is_empty()
+            || !self.value_deny.is_empty()
+            || self.value_allow.is_some()
+            || !self.composite_rules.is_empty()
+            || self.max_json_bytes.is_some()
+            || self.max_array_depth.is_some()
+            || self.max_values.is_some()
+            || self.require_json_object
+            || self.reject_duplicate_keys
+            || self.reject_unsafe_numbers
+            || self.reject_control_chars
+    }
+
+    /// Parses a JSON body. Empty bodies yield `None`; invalid UTF-8 or JSON is
+    /// resolved by `non_json_action`, else `on_unparseable`, when body policies
+    /// are active.
+    fn parse_json_body(&self, bytes: &[u8]) -> Result<Option<serde_json::Value>, Block> {
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        let error = match std::str::from_utf8(bytes) {
+            Err(e) => format!("invalid UTF-8 at byte {}", e.valid_up_to()),
+            Ok(text) => match serde_json::from_str(text) {
+                Ok(value) => {
+                    if let Some(error) = self.reject_duplicate_keys.then(|| duplicate_key(text)).flatten() {
+                        return Err(Block::new("Access Denied: Duplicate JSON Key", "duplicate-key",
+                            format!("🛡️ [Immunity] Ambiguous body: {}", error)));
+                    }
+                    return Ok(Some(value));
+                }
+                Err(e) => format!("invalid JSON: {}", e),
+            },
+        };
+
+        match self.non_json_action {
+            Some(NonJsonAction::Block) if self.has_body_policies() => {
+                return Err(Block::new("Access Denied: Non-JSON Body", "non-json-body",
+                    format!("🛡️ [Immunity] Non-JSON body rejected: {}", error)));
+            }
+            Some(NonJsonAction::InspectRaw) => return self.scan_raw(bytes, bytes.len()).map(|()| None),
+            Some(_) => return Ok(None),
+            None => {}
+        }
+
+        let default_action = if !self.has_allowlist() { UnparseableAction::Allow } else { UnparseableAction::Block };
+        match self.on_unparseable.unwrap_or(default_action) {
+            UnparseableAction::Block if self.has_body_policies() => {
+                Err(Block::new("Access Denied: Unparseable Body", "unparseable-body",
+                    format!("🛡️ [Immunity] Unparseable body rejected: {}", error)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Whether a response with this `:status` is subject to redaction. An
+    /// unreadable status only matches when no statuses are configured.
+    fn redacts_status(&self, status: Option<u32>) -> bool {
+        !self.response_redact_paths.is_empty()
+            && (self.response_redact_statuses.is_empty()
+                || status.is_some_and(|s| self.response_redact_statuses.contains(&s)))
+    }
+
+    /// Masks `response_redact_paths` in a JSON response body, decompressing
+    /// and re-compressing gzip. Returns `None` when nothing was masked or the
+    /// body cannot be read, leaving the response untouched.
+    fn redact_response(&self, body: &[u8], encoding: ContentEncoding) -> Option<Vec<u8>> {
+        let plain = match encoding {
+            ContentEncoding::Identity => Cow::Borrowed(body),
+            ContentEncoding::Gzip => Cow::Owned(gunzip(body, self.max_decompressed_bytes)?),
+        };
+        let mut json: serde_json::Value = serde_json::from_slice(&plain).ok()?;
+        let mut masked = 0;
+        for path in &self.response_redact_paths {
+            masked += redact_at_path(&mut json, &path.split('.').collect::<Vec<_>>());
+        }
+        if masked == 0 {
+            return None;
+        }
+        let redacted = self.serialize_json(&json);
+        match encoding {
+            ContentEncoding::Identity => Some(redacted),
+            ContentEncoding::Gzip => gzip(&redacted),
+        }
+    }
+
+    /// JSON for a body the filter rewrote (stripped, scrubbed or redacted).
+    /// Key order and layout are the operator's choice, for upstreams and
+    /// clients that sign or diff the exact bytes; the client's own spacing
+    /// is never recoverable.
+    fn serialize_json(&self, value: &serde_json::Value) -> Vec<u8> {
+        let value = if self.preserve_key_order {
+            Cow::Borrowed(value)
+        } else {
+            let mut sorted = value.clone();
+            sorted.sort_all_objects();
+            Cow::Owned(sorted)
+        };
+        let encoded = if self.minify { serde_json::to_vec(&*value) } else { serde_json::to_vec_pretty(&*value) };
+        encoded.unwrap_or_default()
+    }
+
+    /// A gzip request body decoded for inspection, so limits such as
+    /// `max_json_bytes` see the logical payload. Other encodings, and gzip
+    /// beyond `max_decompressed_bytes`, are left as sent and fall to the
+    /// unparseable-body handling.
+    fn decode_request_body<'a>(&self, body: &'a [u8], encoding: Option<ContentEncoding>) -> Cow<'a, [u8]> {
+        match encoding {
+            Some(ContentEncoding::Gzip) => gunzip(body, self.max_decompressed_bytes).map_or(Cow::Borrowed(body), Cow::Owned),
+            _ => Cow::Borrowed(body),
+        }
+    }
+
+    /// The action to return after attempting a local reply, and whether the
+    /// request stream must be reset because the reply was not sent.
+    fn action_after_send(&self, sent: Result<(), Status>) -> (Action, bool) {
+        match (sent, self.send_failure_action) {
+            (Ok(()), _) => (Action::Pause, false),
+            (Err(_), SendFailureAction::Reset) => (Action::Pause, true),
+            (Err(_), SendFailureAction::Continue) => (Action::Continue, false),
+        }
+    }
+
+    /// With `allow_paths` set, an empty body carries no allowed fields at all;
+    /// `empty_body_action: deny` refuses it rather than letting it through.
+    /// With `required_paths` it always lacks them, so it is refused either way.
+    fn check_empty_body(&self, body_size: usize) -> Result<(), Block> {
+        let deny = self.empty_body_action == EmptyBodyAction::Deny || !self.required_paths.is_empty();
+        if body_size == 0 && self.has_allowlist() && deny {
+            return Err(Block::new("Access Denied: Body Required", "empty-body",
+                "🛡️ [Immunity] Empty body rejected under strict allowlist".to_string()));
+        }
+        Ok(())
+    }
+
+    /// A request whose headers end the stream (a GET, `content-length: 0`)
+    /// never reaches the body callback, so its absent body is judged here.
+    fn check_bodyless(&self, end_of_stream: bool) -> Result<(), Block> {
+        if end_of_stream {
+            return self.check_empty_body(0);
+        }
+        Ok(())
+    }
+
+    /// Exact-payload match against a feed of known-bad body hashes. Any byte
+    /// of difference (even whitespace) evades it; it complements the rules.
+    fn check_body_hash(&self, body: &[u8]) -> Result<(), Block> {
+        if self.body_hash_denylist.is_empty() {
+            return Ok(());
+        }
+        let digest = sha256_hex(body);
+        if self.body_hash_denylist.contains(&digest) {
+            return Err(Block::new("Access Denied: Known-Bad Payload", "known-bad-body",
+                format!("🛡️ [Immunity] Body matches known-bad hash {}", digest))
+                .with_rule(digest));
+        }
+        Ok(())
+    }
+
+    /// Pre-parse scan of the first `raw_scan_max_bytes` of the body as text,
+    /// catching payloads in bodies that never parse or are not JSON at all.
+    fn inspect_raw_body(&self, body: &[u8]) -> Result<(), Block> {
+        self.scan_raw(body, self.raw_scan_max_bytes)
+    }
+
+    fn scan_raw(&self, body: &[u8], limit: usize) -> Result<(), Block> {
+        if self.body_raw_deny.is_empty() {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&body[..body.len().min(limit)]);
+        if let Some(pattern) = self.body_raw_deny.iter().find(|re| re.is_match(&text)) {
+            return Err(Block::new("Access Denied: Raw Body Pattern", "raw-body-match",
+                format!("🛡️ [Methylation] Raw body matched deny pattern {}", pattern))
+                .with_rule(pattern.as_str()));
+        }
+        Ok(())
+    }
+
+    /// With `enforce_content_type`, a body declared as JSON must parse, and a
+    /// body declared as anything else must not be a JSON object or array.
+    /// Empty and whitespace-only bodies never mismatch.
+    fn check_content_type(&self, content_type: Option<&str>, body: &[u8]) -> Result<(), Block> {
+        let Some(content_type) = content_type else { return Ok(()) };
+        if !self.enforce_content_type || body.iter().all(u8::is_ascii_whitespace) {
+            return Ok(());
+        }
+        let parsed = std::str::from_utf8(body).ok()
+            .and_then(|text| serde_json::from_str::<serde_json::Value>(text).ok());
+        let mismatch = if is_json_content_type(content_type) {
+            parsed.is_none()
+        } else if is_csv_content_type(content_type) || is_grpc_content_type(content_type) {
+            false
+        } else {
+            matches!(parsed, Some(serde_json::Value::Object(_) | serde_json::Value::Array(_)))
+        };
+        if mismatch {
+            return Err(Block::new("Access Denied: Content-Type Mismatch", "content-type-mismatch",
+                format!("🛡️ [Immunity] Body does not match declared content-type: {}", content_type)));
+        }
+        Ok(())
+    }
+
+    /// CSV bodies: the header row names fields, checked like flattened paths;
+    /// cell values are scanned against `value_deny_regexes` when enabled.
+    fn inspect_csv(&self, body: &[u8]) -> Result<(), Block> {
+        let text = String::from_utf8_lossy(body);
+        let rows = parse_csv(&text);
+        let Some((header, records)) = rows.split_first() else { return Ok(()) };
+
+        for column in header {
+            let column = column.trim().to_lowercase();
+            if self.suppression_paths.contains(&column) {
+                return Err(Block::new("Access Denied: Pathogen Suppressed", "methylated",
+                    format!("🛡️ [Methylation] Suppressed expression of pathogen CSV column: {}", column))
+                    .with_rule(column));
+            }
+        }
+
+        if self.csv_scan_values {
+            self.check_denied_values(records.iter().flatten().map(String::as_str))?;
+        }
+        Ok(())
+    }
+
+    /// Multipart bodies: each part's field name is checked like a flattened
+    /// path. File parts are only size-limited; text parts are optionally
+    /// scanned against `value_deny_regexes`.
+    fn inspect_multipart(&self, content_type: &str, body: &[u8]) -> Result<(), Block> {
+        let Some(boundary) = multipart_boundary(content_type) else { return Ok(()) };
+
+        for part in parse_multipart(body, &boundary) {
+            if let Some(name) = part.name.as_deref().map(str::to_lowercase) {
+                if self.suppression_paths.contains(&name) {
+                    return Err(Block::new("Access Denied: Pathogen Suppressed", "methylated",
+                        format!("🛡️ [Methylation] Suppressed expression of pathogen form field: {}", name))
+                        .with_rule(name));
+                }
+            }
+
+            if part.is_file() {
+                if let Some(limit) = self.max_multipart_file_bytes.filter(|limit| part.data.len() > *limit) {
+                    return Err(Block::new("Access Denied: Upload Too Large", "multipart-file-too-large",
+                        format!("🛡️ [Immunity] Multipart file part too large: {} bytes (limit {})", part.data.len(), limit)));
+                }
+            } else if self.multipart_scan_values {
+                self.check_denied_values([String::from_utf8_lossy(part.data).as_ref()])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// grpc-web-text bodies: message frames stay opaque; metadata carried in
+    /// trailer frames is held to the header rules. Undecodable bodies pass,
+    /// since the upstream will reject them anyway.
+    fn inspect_grpc_web_text(&self, body: &[u8]) -> Result<(), Block> {
+        let Some(decoded) = decode_grpc_web_text(body) else { return Ok(()) };
+        for (flags, payload) in grpc_web_frames(&decoded) {
+            if flags & GRPC_WEB_TRAILER_FLAG != 0 {
+                self.inspect_headers(&parse_grpc_web_metadata(payload))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_denied_values<'a>(&self, values: impl IntoIterator<Item = &'a str>) -> Result<(), Block> {
+        for value in values {
+            if let Some(pattern) = self.value_deny.iter().find(|re| re.is_match(value)) {
+                return Err(Block::new("Access Denied: Denied Value Pattern", "denied-value",
+                    format!("🛡️ [Methylation] Value matched deny pattern {}", pattern))
+                    .with_rule(pattern.as_str())
+                    .with_value(value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs every JSON body rule against `json_body`, for embedders holding a
+    /// parsed document. Paths the filter would strip (under
+    /// `violation_threshold` or `scrub_unallowed`) pass.
+    pub fn inspect_json(&self, json_body: &serde_json::Value) -> Result<(), Block> {
+        self.inspect_json_observed(json_body, |_| {}).map(|_| ())
+    }
+
+    /// `inspect_json`, reporting the flattened path count to `observe` before
+    /// any path rule runs, so the count is known whatever the decision. On
+    /// success, returns the paths the caller strips from the body.
+    fn inspect_json_observed(&self, json_body: &serde_json::Value, observe: impl FnOnce(usize)) -> Result<StrippedPaths, Block> {
+        if self.require_json_object && !json_body.is_object() {
+            return Err(Block::new("Access Denied: Expected JSON Object", "expected-object",
+                "🛡️ [Immunity] Top-level JSON value is not an object".to_string()));
+        }
+
+        if self.reject_unsafe_numbers && has_unsafe_number(json_body) {
+            return Err(Block::new("Access Denied: Unsafe JSON Number", "unsafe-number",
+                "🛡️ [Immunity] Number outside the exactly representable integer range".to_string()));
+        }
+
+        if self.reject_control_chars && has_control_chars(json_body) {
+            return Err(Block::new("Access Denied: Control Character", "control-character",
+                "🛡️ [Immunity] Control character in a JSON key or string".to_string()));
+        }
+
+        if let Some(limit) = self.max_json_bytes {
+            let size = serialized_len(json_body);
+            if size > limit {
+                return Err(Block::new("Access Denied: JSON Payload Too Large", "json-too-large",
+                    format!("🛡️ [Immunity] Logical JSON payload too large: {} bytes (limit {})", size, limit)));
+            }
+        }
+
+        if let Some(limit) = self.max_array_depth {
+            let depth = array_depth(json_body);
+            if depth > limit {
+                return Err(Block::new("Access Denied: Arrays Nested Too Deeply", "array-depth-exceeded",
+                    format!("🛡️ [Immunity] Array nesting too deep: {} (limit {})", depth, limit)));
+            }
+        }
+
+        if let Some(limit) = self.max_values {
+            if count_strings_beyond(json_body, limit) {
+                return Err(Block::new("Access Denied: Too Many Values", "too-many-values",
+                    format!("🛡️ [Immunity] More than {} string values in body", limit)));
+            }
+        }
+
+        let flat_paths = flatten_json(json_body, "");
+        observe(flat_paths.len());
+
+        // 1. Epigenetic Suppression
+        let mut tolerated: Vec<String> = Vec::new();
+        let allowed_first = |path: &str| {
+            self.evaluation_order == EvaluationOrder::AllowFirst && self.allow_paths.contains(path)
+        };
+        for param in flat_paths.iter().filter(|p| !allowed_first(p)) {
+            if self.suppression_paths.contains(param) && !tolerated.contains(param) {
+                if tolerated.len() >= self.violation_threshold {
+                    return Err(Block::new("Access Denied: Pathogen Suppressed", "methylated",
+                        format!("🛡️ [Methylation] Suppressed expression of pathogen path: {}", param))
+                        .with_rule(param));
+                }
+                tolerated.push(param.clone());
+            }
+        }
+        for (path, values) in self.suppression_values.iter().filter(|(p, _)| !allowed_first(p)) {
+            if let Some(hit) = values_at_path(json_body, path).into_iter().find(|v| values.contains(v)) {
+                return Err(Block::new("Access Denied: Pathogen Suppressed", "methylated",
+                    format!("🛡️ [Methylation] Suppressed value at pathogen path: {}", path))
+                    .with_rule(path)
+                    .with_value(scalar_text(hit).unwrap_or_else(|| hit.to_string())));
+            }
+        }
+        for (path, depth) in &self.suppression_depths {
+            let hit = flat_paths.iter().filter(|p| !allowed_first(p)).find(|param| {
+                param.matches('.').count() + 1 == *depth
+                    && (*param == path || param.strip_suffix(path.as_str()).is_some_and(|rest| rest.ends_with('.')))
+            });
+            if let Some(param) = hit {
+                return Err(Block::new("Access Denied: Pathogen Suppressed", "methylated",
+                    format!("🛡️ [Methylation] Suppressed pathogen path {} at depth {}", param, depth))
+                    .with_rule(path));
+            }
+        }
+        for (path, expected) in self.suppression_types.iter().filter(|(p, _)| !allowed_first(p)) {
+            if let Some(hit) = leaf_values(values_at_path(json_body, path)).into_iter().find(|v| expected.admits(v)) {
+                return Err(Block::new("Access Denied: Pathogen Suppressed", "methylated",
+                    format!("🛡️ [Methylation] Suppressed {:?} at pathogen path: {}", expected, path))
+                    .with_rule(path)
+                    .with_value(scalar_text(hit).unwrap_or_else(|| hit.to_string())));
+            }
+        }
+
+        // 1. Suppression globs: the most specific matching glob decides
+        if !self.glob_rules.is_empty() {
+            for param in flat_paths.iter().filter(|p| !allowed_first(p)) {
+                if let Some(rule) = self.deciding_glob(param).filter(|r| !r.exception) {
+                    return Err(Block::new("Access Denied: Pathogen Suppressed", "methylated",
+                        format!("🛡️ [Methylation] Path {} matched suppression glob {}", param, rule.glob))
+                        .with_rule(rule.glob.as_str()));
+                }
+            }
+        }
+
+        // 1a. Denied key names, wherever they appear
+        if !self.deny_key_names.is_empty() {
+            for param in flat_paths.iter().filter(|p| !allowed_first(p)) {
+                let key = param.rsplit('.').next().unwrap_or(param).to_lowercase();
+                if self.deny_key_names.contains(&key) {
+                    return Err(Block::new("Access Denied: Pathogen Suppressed", "methylated",
+                        format!("🛡️ [Methylation] Denied key name at {}", param))
+                        .with_rule(key));
+                }
+            }
+        }
+
+        // 1b. Denied array elements
+        for (path, denied) in &self.array_value_deny {
+            for found in values_at_path(json_body, path) {
+                let Some(elements) = found.as_array() else { continue };
+                if let Some(hit) = elements.iter().filter_map(scalar_text).find(|v| denied.contains(v)) {
+                    return Err(Block::new("Access Denied: Denied Array Value", "denied-array-value",
+                        format!("🛡️ [Methylation] Denied array value at {}", path))
+                        .with_rule(path)
+                        .with_value(hit));
+                }
+            }
+        }
+
+        // 1c. Denied value patterns
+        if !self.value_deny.is_empty() {
+            let mut strings = Vec::new();
+            collect_strings(json_body, &mut strings);
+            self.check_denied_values(strings)?;
+        }
+
+        // 1c'. Positive model: every value must conform
+        if let Some(allow) = &self.value_allow {
+            let mut strings = Vec::new();
+            collect_strings(json_body, &mut strings);
+            if let Some(value) = strings.into_iter().find(|v| !allow.is_match(v)) {
+                return Err(Block::new("Access Denied: Value Not Allowed", "value-not-allowed",
+                    format!("⚔️ [Immunity] Value does not match value_allow_regex {}", allow))
+                    .with_rule(allow.as_str())
+                    .with_value(value));
+            }
+        }
+
+        // 1d. Composite (AND) rules
+        for rule in &self.composite_rules {
+            if !rule.all_of.iter().all(|c| c.holds(json_body)) {
+                continue;
+            }
+            if rule.action == RuleAction::Deny {
+                let paths: Vec<&str> = rule.all_of.iter().map(|c| c.path.as_str()).collect();
+                return Err(Block::new("Access Denied: Composite Rule", "composite-rule",
+                    format!("🛡️ [Methylation] Composite rule matched: {}", paths.join(" & ")))
+                    .with_rule(paths.join("&")));
+            }
+            break;
+        }
+
+        // 2. Hierarchical Purity (tolerated paths are stripped, so not checked)
+        let mut scrubbed: Vec<String> = Vec::new();
+        if self.has_allowlist() {
+            let mut required: Vec<&String> = self.required_paths.iter().filter(|p| !flat_paths.contains(p)).collect();
+            required.sort();
+            if let Some(missing) = required.first() {
+                return Err(Block::new("Access Denied: Required Path Missing", "missing-required-path",
+                    format!("⚔️ [Immunity] Required path absent from body: {}", missing))
+                    .with_rule(missing.as_str()));
+            }
+            let array_roots: Vec<String> = self.allow_arrays.iter()
+                .filter(|path| {
+                    let found = values_at_path(json_body, path);
+                    !found.is_empty() && found.iter().all(|v| v.is_array())
+                })
+                .cloned()
+                .collect();
+            for param in &flat_paths {
+                if !self.is_allowed_path(param) && !is_within_any(param, &tolerated) && !is_within_any(param, &array_roots) {
+                    let in_query = self.merge_query_params && (param == "query" || param.starts_with("query."));
+                    if self.scrub_unallowed && !in_query {
+                        // Only the outermost foreign path; its subtree goes with it
+                        if !is_within_any(param, &scrubbed) {
+                            scrubbed.push(param.clone());
+                        }
+                        continue;
+                    }
+                    return Err(Block::new("Access Denied: Foreign Antigen", "antigen-rejected",
+                        format!("⚔️ [Immunity] Foreign antigen detected (Not in Allow Map): {}", param))
+                        .with_rule(param));
+                }
+            }
+            // A constrained path may be absent; when present, every value must be permitted.
+            for (path, permitted) in &self.allow_values {
+                let mut found = values_at_path(json_body, path);
+                if self.allow_arrays.contains(path) {
+                    found = found.into_iter()
+                        .flat_map(|v| v.as_array().map_or_else(|| vec![v], |elements| elements.iter().collect()))
+                        .collect();
+                }
+                for found in found {
+                    let text = scalar_text(found);
+                    if !text.as_ref().is_some_and(|v| permitted.contains(v)) {
+                        return Err(Block::new("Access Denied: Value Not Allowed", "value-not-allowed",
+                            format!("⚔️ [Immunity] Value not in Allow Map at {}", path))
+                            .with_rule(path)
+                            .with_value(text.unwrap_or_else(|| found.to_string())));
+                    }
+                }
+            }
+            for (path, expected) in &self.allow_types {
+                if let Some(found) = leaf_values(values_at_path(json_body, path)).into_iter().find(|v| !expected.admits(v)) {
+                    return Err(Block::new("Access Denied: Unexpected Value Type", "unexpected-type",
+                        format!("⚔️ [Immunity] Expected {:?} at {}", expected, path))
+                        .with_rule(path)
+                        .with_value(scalar_text(found).unwrap_or_else(|| found.to_string())));
+                }
+            }
+        }
+
+        Ok(StrippedPaths { tolerated, scrubbed })
+    }
+
+    /// The matching glob with the most literal characters. On a tie the
+    /// suppression wins, so an equally specific exception cannot reopen a path.
+    fn deciding_glob(&self, path: &str) -> Option<&GlobRule> {
+        let path = path.to_lowercase();
+        self.glob_rules.iter()
+            .filter(|rule| rule.regex.is_match(&path))
+            .max_by_key(|rule| (rule.specificity, !rule.exception))
+    }
+
+    /// `ok`, or which rules `compile` skipped.
+    fn compile_status(&self) -> String {
+        if self.compile_errors.is_empty() {
+            "ok".to_string()
+        } else {
+            format!("error: {} invalid rule(s): {}", self.compile_errors.len(), self.compile_errors.join("; "))
+        }
+    }
+
+    /// Active rule counts by kind, for the policy gauges and the self-test.
+    fn rule_counts(&self) -> [(&'static str, usize); 4] {
+        [
+            ("suppression", self.suppression_entries.iter().filter(|e| e.enabled()).count() + self.suppression_globs.len()),
+            ("allow", self.allow_entries.iter().filter(|e| e.enabled()).count()),
+            ("deny_key_names", self.deny_key_names.len()),
+            ("value_deny", self.value_deny_regexes.len()),
+        ]
+    }
+
+    /// Whether a request's `self_test` header value asks for the self-test.
+    fn is_self_test(&self, value: impl FnOnce(&str) -> Option<String>) -> bool {
+        self.self_test.as_ref().is_some_and(|probe| value(&probe.header).is_some_and(|v| v == probe.value))
+    }
+
+    /// The self-test reply body: the config status and rule counts.
+    fn self_test_report(&self) -> Vec<u8> {
+        let rules: serde_json::Map<String, serde_json::Value> = self.rule_counts().into_iter()
+            .map(|(kind, count)| (kind.to_string(), count.into()))
+            .collect();
+        serde_json::json!({ "status": self.compile_status(), "rules": rules }).to_string().into_bytes()
+    }
+
+    /// Exact membership, or an allowed ancestor no more than
+    /// `allow_prefix_depth` levels above `path` (any level when `None`).
+    fn is_allowed_path(&self, path: &str) -> bool {
+        if self.allow_paths.contains(path) || self.allowed_globs.iter().any(|re| re.is_match(path)) {
+            return true;
+        }
+        let mut ancestor = path;
+        let mut depth = 0;
+        while let Some((parent, _)) = ancestor.rsplit_once('.') {
+            depth += 1;
+            if self.allow_prefix_depth.is_some_and(|limit| depth > limit) {
+                return false;
+            }
+            if self.allow_paths.contains(parent) {
+                return true;
+            }
+            ancestor = parent;
+        }
+        false
+    }
+}
+
+/// Shared-data key a sidecar can scrape: `ok`, or a summary of why loading
+/// failed or which rules were skipped. Written as shared data because the
+/// root context has no stream to carry a property.
+const CONFIG_STATUS_KEY: &str = "leukocyte.config_status";
+
+fn config_status(loaded: &Result<PolicyConfig, String>) -> String {
+    match loaded {
+        Ok(config) => config.compile_status(),
+        Err(error) => format!("error: {}", error),
+    }
+}
+
+/// VM-wide settings from the VM configuration, read once at `on_vm_start`.
+/// They hold for every plugin policy loaded into the VM, configured or pushed.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+struct VmConfig {
+    #[serde(default)]
+    log_level: Option<VmLogLevel>,      // Default: everything the host accepts
+    #[serde(default)]
+    shared_data_failure: Option<SharedDataFailure>, // Overrides each policy's own setting
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum VmLogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Off,
+}
+
+impl VmConfig {
+    /// An absent or empty VM configuration means the defaults.
+    fn parse(bytes: Option<&[u8]>) -> Result<Self, String> {
+        match bytes {
+            Some(bytes) if !bytes.iter().all(u8::is_ascii_whitespace) => {
+                serde_json::from_slice(bytes).map_err(|e| e.to_string())
+            }
+            _ => Ok(VmConfig::default()),
+        }
+    }
+
+    fn apply_log_level(&self) {
+        let Some(level) = self.log_level else { return };
+        log::set_max_level(match level {
+            VmLogLevel::Trace => log::LevelFilter::Trace,
+            VmLogLevel::Debug => log::LevelFilter::Debug,
+            VmLogLevel::Info => log::LevelFilter::Info,
+            VmLogLevel::Warn => log::LevelFilter::Warn,
+            VmLogLevel::Error => log::LevelFilter::Error,
+            VmLogLevel::Off => log::LevelFilter::Off,
+        });
+    }
+
+    /// Imposes the VM-wide toggles on a freshly loaded policy and its sub-policies.
+    fn apply(&self, config: &mut PolicyConfig) {
+        let Some(failure) = self.shared_data_failure else { return };
+        config.shared_data_failure = failure;
+        for sub in &mut config.resolved_sub_policies {
+            Rc::make_mut(&mut sub.policy).shared_data_failure = failure;
+        }
+    }
+}
+
+struct LeukocyteRoot {
+    config: Rc<PolicyConfig>,
+    vm: VmConfig,
+    metrics: Option<Metrics>,
+    incident_queue: Option<u32>,
+    config_queue: Option<u32>,
+}
+
+impl LeukocyteRoot {
+    /// Replaces the active policy with one pushed by the control plane.
+    /// In-flight requests keep the policy they started with; a malformed
+    /// message leaves the current policy in place.
+    fn apply_pushed_config(&mut self, bytes: &[u8]) -> bool {
+        match PolicyConfig::load(bytes) {
+            Ok(mut config) => {
+                self.vm.apply(&mut config);
+                self.config = Rc::new(config);
+                true
+            }
+            Err(error) => {
+                warn!("{}", self.config.log_text(&format!("⚠️ [Leukocyte] Ignoring pushed configuration: {}", error)));
+                false
+            }
+        }
+    }
+
+    /// Sets the policy gauges from the active policy.
+    fn record_policy_size(&self, config_bytes: usize) {
+        let Some(metrics) = self.metrics else { return };
+        for (id, value) in metrics.policy_samples(&self.config, config_bytes) {
+            let _ = proxy_wasm::hostcalls::record_metric(id, value);
+        }
+    }
+
+    /// Records the outcome of a load under `CONFIG_STATUS_KEY` through `set`.
+    /// A host error is logged; it never fails the load.
+    fn publish_config_status(&self, loaded: &Result<PolicyConfig, String>, set: impl FnOnce(&str, &[u8]) -> Result<(), Status>) {
+        if let Err(status) = set(CONFIG_STATUS_KEY, config_status(loaded).as_bytes()) {
+            warn!("{}", self.config.log_text(&format!("⚠️ [Leukocyte] Could not publish the config status: {:?}", status)));
+        }
+    }
+
+    /// A fresh filter bound to the active policy.
+    fn filter_for(&self, context_id: u32) -> LeukocyteFilter {
+        LeukocyteFilter {
+            config: self.config.clone(),
+            metrics: self.metrics.unwrap_or_default(),
+            incident_queue: self.incident_queue,
+            rng: Rng::for_context(self.config.rng_seed, context_id),
+            inspect_body: true,
+            body_kind: None,
+            content_type: None,
+            request: RequestInfo::default(),
+            passthrough: None,
+            summary: RequestSummary::default(),
+            response_encoding: None,
+            query: None,
+            expects_continue: false,
+        }
+    }
+}
+
+/// Host metric ids, defined once by the root context and copied into filters.
+#[derive(Debug, Clone, Copy, Default)]
+struct Metrics {
+    inspected: Option<u32>,
+    passthrough: [Option<u32>; 5],     // Indexed by `Passthrough as usize`
+    severity_blocks: [Option<u32>; 4], // Indexed by `Severity as usize`
+    inspection_ms: Option<u32>,        // Histogram
+    policy: [Option<u32>; 5],          // Gauges, in `POLICY_GAUGES` order
+}
+
+/// Gauges describing the active policy, set each time one loads.
+const POLICY_GAUGES: [&str; 5] = [
+    "leukocyte_config_bytes",
+    "leukocyte_suppression_rules",
+    "leukocyte_allow_rules",
+    "leukocyte_deny_key_names",
+    "leukocyte_value_deny_rules",
+];
+
+/// Why a request skipped full inspection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Passthrough {
+    HealthCheck,
+    Preflight,
+    SampledOut,
+    ContentType,
+    WebSocket,
+}
+
+impl Passthrough {
+    fn label(self) -> &'static str {
+        match self {
+            Passthrough::HealthCheck => "health_check",
+            Passthrough::Preflight => "preflight",
+            Passthrough::SampledOut => "sampled_out",
+            Passthrough::ContentType => "content_type",
+            Passthrough::WebSocket => "websocket",
+        }
+    }
+}
+
+impl Metrics {
+    fn define() -> Self {
+        Metrics {
+            inspected: define_counter("leukocyte_inspected"),
+            passthrough: [
+                define_counter("leukocyte_passthrough_health_check"),
+                define_counter("leukocyte_passthrough_preflight"),
+                define_counter("leukocyte_sampled_out"),
+                define_counter("leukocyte_passthrough_content_type"),
+                define_counter("leukocyte_passthrough_websocket"),
+            ],
+            severity_blocks: [
+                define_counter("leukocyte_group_blocks_low"),
+                define_counter("leukocyte_group_blocks_medium"),
+                define_counter("leukocyte_group_blocks_high"),
+                define_counter("leukocyte_group_blocks_critical"),
+            ],
+            inspection_ms: proxy_wasm::hostcalls::define_metric(MetricType::Histogram, "leukocyte_inspection_ms").ok(),
+            policy: POLICY_GAUGES.map(|name| proxy_wasm::hostcalls::define_metric(MetricType::Gauge, name).ok()),
+        }
+    }
+
+    /// Gauge ids and values for a policy loaded from `config_bytes` bytes.
+    fn policy_samples(&self, config: &PolicyConfig, config_bytes: usize) -> Vec<(u32, u64)> {
+        let values = std::iter::once(config_bytes).chain(config.rule_counts().map(|(_, count)| count));
+        self.policy.iter().zip(values)
+            .filter_map(|(id, value)| id.map(|id| (id, value as u64)))
+            .collect()
+    }
+
+    /// The counter recording how a finished request was handled.
+    fn outcome(&self, passthrough: Option<Passthrough>) -> Option<u32> {
+        match passthrough {
+            Some(reason) => self.passthrough[reason as usize],
+            None => self.inspected,
+        }
+    }
+
+    /// The histogram id and elapsed milliseconds for one body inspection.
+    fn inspection_sample(&self, started: std::time::SystemTime, finished: std::time::SystemTime) -> Option<(u32, u64)> {
+        let id = self.inspection_ms?;
+        let elapsed = finished.duration_since(started).map_or(0, |d| d.as_millis() as u64);
+        Some((id, elapsed))
+    }
+}
+
+fn define_counter(name: &str) -> Option<u32> {
+    proxy_wasm::hostcalls::define_metric(MetricType::Counter, name).ok()
+}
+
+fn increment(metric: Option<u32>) {
+    if let Some(id) = metric {
+        let _ = proxy_wasm::hostcalls::increment_metric(id, 1);
+    }
+}
+
+impl Context for LeukocyteRoot {}
+
+impl RootContext for LeukocyteRoot {
+    /// One-time setup that does not depend on the plugin policy. A malformed
+    /// VM configuration fails the start rather than dropping its toggles.
+    fn on_vm_start(&mut self, _vm_configuration_size: usize) -> bool {
+        self.metrics.get_or_insert_with(Metrics::define);
+        match VmConfig::parse(self.get_vm_configuration().as_deref()) {
+            Ok(vm) => {
+                vm.apply_log_level();
+                self.vm = vm;
+                true
+            }
+            Err(error) => {
+                warn!("{}", self.config.log_text(&format!("⚠️ [Leukocyte] Invalid VM configuration: {}", error)));
+                false
+            }
+        }
+    }
+
+    fn on_configure(&mut self, _plugin_configuration_size: usize) -> bool {
+        let mut config_bytes = 0;
+        let loaded = self.get_plugin_configuration()
+            .ok_or_else(|| "no plugin configuration".to_string())
+            .and_then(|bytes| {
+                config_bytes = bytes.len();
+                PolicyConfig::load(&bytes)
+            });
+        self.publish_config_status(&loaded, |key, status| host::set_shared_data(key, Some(status), None));
+        match loaded {
+            Ok(mut config) => {
+                self.vm.apply(&mut config);
+                // Queues are registered from the plugin configuration only;
+                // a pushed policy cannot redirect them.
+                self.incident_queue = config.incident_queue.as_deref()
+                    .and_then(|name| proxy_wasm::hostcalls::register_shared_queue(name).ok());
+                self.config_queue = config.config_queue.as_deref()
+                    .and_then(|name| proxy_wasm::hostcalls::register_shared_queue(name).ok());
+                self.config = Rc::new(config);
+                self.record_policy_size(config_bytes);
+            }
+            Err(error) => {
+                warn!("{}", self.config.log_text(&format!("⚠️ [Leukocyte] Failed to load configuration (Immunity Deficit): {}", error)));
+            }
+        }
+        true
+    }
+
+    fn on_queue_ready(&mut self, queue_id: u32) {
+        if Some(queue_id) != self.config_queue {
+            return;
+        }
+        while let Ok(Some(message)) = proxy_wasm::hostcalls::dequeue_shared_queue(queue_id) {
+            if self.apply_pushed_config(&message) {
+                self.record_policy_size(message.len());
+            }
+        }
+    }
+
+    fn create_http_context(&self, context_id: u32) -> Option<Box<dyn HttpContext>> {
+        Some(Box::new(self.filter_for(context_id)))
+    }
+
+    fn get_type(&self) -> Option<ContextType> {
+        Some(ContextType::HttpContext)
+    }
+}
+
+/// The filter's decision for a request, kept until `on_log`.
+#[derive(Debug, Clone, PartialEq)]
+struct Decision {
+    outcome: String,
+    defense: &'static str,
+    rule: Option<String>,
+}
+
+/// Collects what the `summary_log` line reports and records the decision
+/// once per request.
+#[derive(Debug, Default)]
+struct RequestSummary {
+    path_count: Option<usize>,
+    stripped: bool, // Paths were removed from the forwarded body
+    decision: Option<Decision>,
+}
+
+/// Header stamped with the decision under `debug_decision_header`.
+const DECISION_HEADER: &str = "x-leukocyte-decision";
+
+impl RequestSummary {
+    /// Records the decision on the first call, returning the summary line
+    /// when `summary_log` is on; `None` after.
+    fn finish(&mut self, config: &PolicyConfig, request: &RequestInfo,
+              passthrough: Option<Passthrough>, block: Option<&Block>) -> Option<String> {
+        if self.decision.is_some() {
+            return None;
+        }
+        let decision = self.decision.insert(Decision {
+            outcome: match (block, passthrough) {
+                (Some(_), _) => "block".to_string(),
+                (None, Some(reason)) => format!("passthrough:{}", reason.label()),
+                (None, None) if self.stripped => "scrub".to_string(),
+                (None, None) => "allow".to_string(),
+            },
+            defense: block.map_or("-", |b| b.defense),
+            rule: block.and_then(|b| b.rule.clone()),
+        });
+        if !config.summary_log {
+            return None;
+        }
+
+        let mut line = format!("🧬 [Leukocyte] Request summary | decision={} defense={} rule={} paths={} method={}",
+            decision.outcome,
+            decision.defense,
+            config.report(decision.rule.as_deref().unwrap_or("-")),
+            self.path_count.map_or("-".to_string(), |n| n.to_string()),
+            request.method.as_deref().unwrap_or("-"));
+        if config.reveal_match {
+            line.push_str(&format!(" path={}", config.report(request.path.as_deref().unwrap_or("-"))));
+        }
+        Some(config.log_text(&line).to_string())
+    }
+
+    /// The recorded outcome for `DECISION_HEADER`, only under `debug_decision_header`.
+    fn decision_header<'a>(&'a self, config: &PolicyConfig) -> Option<&'a str> {
+        if !config.debug_decision_header {
+            return None;
+        }
+        self.decision.as_ref().map(|d| d.outcome.as_str())
+    }
+
+    /// The `decision_log` line for `on_log`. A stream that ended before any
+    /// decision (e.g. a client reset mid-body) reports `incomplete`.
+    fn decision_log_line(&self, config: &PolicyConfig, request: &RequestInfo) -> Option<String> {
+        if !config.decision_log {
+            return None;
+        }
+        let (outcome, defense, rule) = self.decision.as_ref()
+            .map_or(("incomplete", "-", None), |d| (d.outcome.as_str(), d.defense, d.rule.as_deref()));
+        let line = format!("🧬 [Leukocyte] Final decision | request_id={} decision={} defense={} rule={}",
+            request.request_id.as_deref().unwrap_or("-"),
+            outcome,
+            defense,
+            config.report(rule.unwrap_or("-")));
+        Some(config.log_text(&line).to_string())
+    }
+}
+
+/// Dynamic metadata key carrying the flattened path count of a JSON body.
+const PATH_COUNT_PROPERTY: [&str; 2] = ["leukocyte", "path_count"];
+/// Dynamic metadata key tagging a request whose suppressed paths were stripped.
+const VIOLATIONS_PROPERTY: [&str; 2] = ["leukocyte", "violations"];
+
+struct LeukocyteFilter {
+    config: Rc<PolicyConfig>,
+    metrics: Metrics,
+    incident_queue: Option<u32>,
+    rng: Rng,
+    inspect_body: bool,
+    body_kind: Option<BodyKind>,
+    content_type: Option<String>,
+    request: RequestInfo,
+    passthrough: Option<Passthrough>,
+    summary: RequestSummary,
+    response_encoding: Option<ContentEncoding>, // Set when the response body will be redacted
+    query: Option<serde_json::Value>,   // Parsed under `merge_query_params`, merged at inspection
+    expects_continue: bool,             // `Expect: 100-continue`; headers are never held
+}
+
+impl Context for LeukocyteFilter {}
+
+impl LeukocyteFilter {
+    fn is_grpc(&self) -> bool {
+        self.content_type.as_deref().is_some_and(is_grpc_content_type)
+    }
+
+    /// Enqueues one incident record through `enqueue`. Best effort: any
+    /// host error, such as a full or missing queue, is logged at debug level
+    /// and never affects traffic.
+    fn report_incident(&self, block: &Block, timestamp: u64, enqueue: impl FnOnce(u32, &[u8]) -> Result<(), Status>) {
+        let Some(queue_id) = self.incident_queue else { return };
+        let record = self.config.incident_record(block, &self.request, timestamp);
+        if let Err(status) = enqueue(queue_id, &record) {
+            debug!("{}", self.config.log_text(&format!("⚠️ [Leukocyte] Incident enqueue failed: {:?}", status)));
+        }
+    }
+
+    /// Best effort, like incident reporting: a host error, or contention
+    /// beyond a few retries, drops the entry.
+    fn remember_block(&self, block: &Block, timestamp: u64) {
+        let Some(recent) = &self.config.recent_blocks else { return };
+        let entry = self.config.recent_block_entry(block, &self.request, timestamp);
+        for _ in 0..3 {
+            let Ok((data, cas)) = host::get_shared_data(RECENT_BLOCKS_KEY) else { return };
+            let next = recent.record(data.as_deref(), entry.clone());
+            match host::set_shared_data(RECENT_BLOCKS_KEY, Some(&next), cas) {
+                Err(Status::CasMismatch) => continue,
+                _ => return,
+            }
+        }
+    }
+
+    /// Captures who sent the request and switches to its sub-policy, if any.
+    fn identify_request(&mut self) {
+        self.content_type = self.get_http_request_header("content-type");
+        let source = self.get_property(vec!["source", "address"])
+            .and_then(|b| String::from_utf8(b).ok());
+        self.request = self.config.request_info(source, |name| self.get_http_request_header(name));
+        if let Some(policy) = self.config.sub_policy_for(&self.request) {
+            self.config = policy;
+        }
+        let forwarded_for = self.get_http_request_header("x-forwarded-for");
+        self.request.client = Some(self.config.client_address(
+            self.request.source.as_deref().unwrap_or("unknown"), forwarded_for.as_deref()));
+    }
+
+    /// Answers the `recent_blocks` route with the stored buffer, or an empty
+    /// list when it cannot be read. The rate limit and `required_headers`
+    /// apply first, as they would to any other request.
+    fn serve_recent_blocks(&mut self) -> Option<Action> {
+        let recent = self.config.recent_blocks.as_ref()?;
+        if self.request.route != recent.route {
+            return None;
+        }
+        let headers = self.get_http_request_headers();
+        if let Err(block) = self.check_rate_limit().and_then(|()| self.config.check_required_headers(&headers)) {
+            let action = self.send_denial(&block);
+            self.log_summary(Some(&block));
+            return Some(action);
+        }
+        let body = host::get_shared_data(RECENT_BLOCKS_KEY).ok()
+            .and_then(|(data, _)| data)
+            .unwrap_or_else(|| b"[]".to_vec());
+        Some(self.send_local_reply(200, vec![("content-type", "application/json")], Some(&body)))
+    }
+
+    /// Sends a local reply through `host`, which returns the host's status
+    /// where the `Context` wrapper would trap. A failed send is logged and
+    /// resolved by `send_failure_action`.
+    fn send_local_reply(&self, status: u32, headers: Vec<(&str, &str)>, body: Option<&[u8]>) -> Action {
+        let sent = host::send_http_response(status, &headers, body);
+        if let Err(error) = &sent {
+            warn!("{}", self.config.log_text(&format!("⚠️ [Leukocyte] Local reply failed ({:?}); applying {:?}",
+                error, self.config.send_failure_action)));
+        }
+        let (action, reset) = self.config.action_after_send(sent);
+        if reset {
+            let _ = host::reset_http_request();
+        }
+        action
+    }
+
+    fn send_denial(&mut self, block: &Block) -> Action {
+        warn!("{}", self.config.block_log_line(block, &self.request));
+        if let Some(severity) = block.severity {
+            increment(self.metrics.severity_blocks[severity as usize]);
+        }
+        let timestamp = self.get_current_time()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        self.report_incident(block, timestamp, host::enqueue_shared_queue);
+        self.remember_block(block, timestamp);
+
+        let grpc_reply = self.content_type.as_deref().is_some_and(expects_grpc_reply);
+        let response = self.config.render_block(block, &self.request, grpc_reply);
+        let headers: Vec<(&str, &str)> = response.headers.iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        self.send_local_reply(response.status, headers, response.body.as_deref())
+    }
+}
+
+impl LeukocyteFilter {
+    /// Header-phase evaluation. Also decides whether the body will be inspected.
+    fn evaluate_request_headers(&mut self, end_of_stream: bool) -> Result<(), Block> {
+        let headers = self.get_http_request_headers();
+        self.body_kind = self.config.body_kind(self.content_type.as_deref());
+        self.passthrough = self.config.passthrough_for(&self.request, &headers, self.body_kind, end_of_stream,
+            || self.rng.next_f64());
+        self.inspect_body = self.body_kind.is_some() && self.passthrough.is_none();
+        if self.passthrough == Some(Passthrough::HealthCheck) {
+            return Ok(());
+        }
+
+        self.check_rate_limit()?;
+
+        self.expects_continue = expects_continue(&headers);
+
+        self.config.inspect_headers(&headers)?;
+        self.config.check_required_headers(&headers)?;
+        for name in self.config.headers_to_strip(&headers, self.inspect_body) {
+            self.set_http_request_header(name, None);
+        }
+
+        if let Some(uri) = self.request.path.as_deref() {
+            self.config.inspect_uri(uri)?;
+        }
+        self.query = self.config.query_document(self.request.path.as_deref());
+        self.config.check_route_allowed(&self.request.route)?;
+        self.config.check_host_allowed(self.request.host.as_deref())?;
+        let origin = self.get_http_request_header("origin");
+        let referer = self.get_http_request_header("referer");
+        self.config.check_origin(self.request.method.as_deref(), origin.as_deref(), referer.as_deref())?;
+
+        let property = |path: Vec<&str>| {
+            self.get_property(path).map(|b| String::from_utf8_lossy(&b).into_owned())
+        };
+        let tls_version = property(vec!["connection", "tls_version"]);
+        let sni = property(vec!["connection", "requested_server_name"]);
+        self.config.inspect_tls(tls_version.as_deref(), sni.as_deref())?;
+        self.config.inspect_protocol(property(vec!["request", "protocol"]).as_deref())?;
+
+        let explicitly_allowed = self.config.inspect_properties(|path| property(path.to_vec()))?;
+
+        if self.passthrough == Some(Passthrough::Preflight) {
+            return Ok(());
+        }
+
+        let body_allowlisted = self.inspect_body && !end_of_stream && self.config.has_allowlist();
+        self.config.check_default_action(explicitly_allowed, body_allowlisted)?;
+        self.config.check_bodyless(end_of_stream)?;
+
+        // Rule groups run once: here when no body will be inspected, otherwise with the body.
+        if !self.inspect_body || end_of_stream {
+            self.inspect_query_alone()?;
+            self.config.evaluate_groups(&self.request, None)?;
+        }
+        Ok(())
+    }
+
+    /// Body rules over the merged query params when no body will be inspected.
+    fn inspect_query_alone(&self) -> Result<(), Block> {
+        match with_query(None, self.query.as_ref()) {
+            Some(document) => self.config.inspect_json_observed(&document, |_| {}).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    fn log_summary(&mut self, block: Option<&Block>) {
+        if let Some(line) = self.summary.finish(&self.config, &self.request, self.passthrough, block) {
+            info!("{}", line);
+        }
+    }
+
+    /// Counts the request against the client's window. Shared data goes
+    /// through `host`, so a host error reaches `shared_data_failure` instead
+    /// of trapping the VM.
+    fn check_rate_limit(&self) -> Result<(), Block> {
+        let Some(limit) = &self.config.rate_limit else { return Ok(()) };
+        let client = self.request.client.as_deref().unwrap_or("unknown");
+        let key = format!("leukocyte_rl:{}", client);
+        let now = self.get_current_time()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+
+        limit.check(client, now, self.config.shared_data_failure,
+            || host::get_shared_data(&key),
+            |value, cas| host::set_shared_data(&key, Some(value), cas))
+    }
+
+    fn evaluate_request_body(&mut self, body_size: usize, end_of_stream: bool) -> Result<Action, Block> {
+        if !self.inspect_body {
+            return Ok(Action::Continue);
+        }
+
+        if !self.config.check_buffer(body_size)? {
+            self.inspect_body = false;
+            self.inspect_query_alone()?;
+            return Ok(Action::Continue);
+        }
+
+        let scannable = self.body_kind.is_some_and(BodyKind::has_json_keys);
+        if let Some(limit) = self.config.inspect_prefix_bytes {
+            if body_size > limit {
+                if let Some(prefix) = self.get_http_request_body(0, limit).filter(|_| scannable) {
+                    self.config.inspect_prefix_window(&prefix)?;
+                }
+                self.inspect_body = false;
+                self.inspect_query_alone()?;
+                return Ok(Action::Continue);
+            }
+        }
+
+        if !end_of_stream {
+            if self.config.streaming_inspection && scannable {
+                if let Some(prefix) = self.get_http_request_body(0, body_size) {
+                    self.config.inspect_partial(&prefix)?;
+                }
+            }
+            return Ok(Action::Pause);
+        }
+
+        self.config.check_empty_body(body_size)?;
+        let wire = self.get_http_request_body(0, body_size).unwrap_or_default();
+        let encoding = ContentEncoding::parse(self.get_http_request_header("content-encoding").as_deref());
+        let body_bytes = self.config.decode_request_body(&wire, encoding);
+        let decoded = matches!(body_bytes, Cow::Owned(_));
+        let parser = parser_for(self.content_type.as_deref());
+        self.config.check_body_hash(&wire)?;
+        self.config.inspect_raw_body(&body_bytes)?;
+        self.config.check_content_type(self.content_type.as_deref(), &body_bytes)?;
+        let json_body = match self.body_kind {
+            Some(BodyKind::Csv) => {
+                self.config.inspect_csv(&body_bytes)?;
+                None
+            }
+            Some(BodyKind::Multipart) => {
+                self.config.inspect_multipart(self.content_type.as_deref().unwrap_or(""), &body_bytes)?;
+                None
+            }
+            Some(BodyKind::GrpcWebText) => {
+                self.config.inspect_grpc_web_text(&body_bytes)?;
+                None
+            }
+            Some(BodyKind::Opaque) => None,
+            _ => {
+                debug!("{}", self.config.log_text(&format!("🧬 [Leukocyte] Parsing body as {}", parser.name())));
+                parser.parse(&self.config, &body_bytes)?
+            }
+        };
+        let mut stripped = StrippedPaths::default();
+        if let Some(json_body) = with_query(json_body.as_ref(), self.query.as_ref()) {
+            let config = Rc::clone(&self.config);
+            let mut path_count = None;
+            let verdict = config.inspect_json_observed(&json_body, |count| {
+                path_count = Some(count);
+                // Exported for access logs as dynamic metadata
+                self.set_property(PATH_COUNT_PROPERTY.to_vec(), Some(count.to_string().as_bytes()));
+            });
+            self.summary.path_count = path_count;
+            stripped = verdict?;
+        }
+        self.config.evaluate_groups(&self.request, json_body.as_ref())?;
+
+        let StrippedPaths { tolerated, scrubbed } = stripped;
+        if let (Some(mut json_body), false) = (json_body, tolerated.is_empty() && scrubbed.is_empty()) {
+            strip_paths(&mut json_body, "", &tolerated);
+            strip_paths(&mut json_body, "", &scrubbed);
+            let encoded = parser.encode(&self.config, &json_body);
+            // Re-compressed so the upstream gets the encoding the client declared
+            let encoded = recompress(encoded, decoded, gzip)?;
+            self.set_http_request_body(0, body_size, &encoded);
+            self.summary.stripped = true;
+            if !tolerated.is_empty() {
+                self.set_property(VIOLATIONS_PROPERTY.to_vec(), Some(tolerated.len().to_string().as_bytes()));
+                warn!("{}", self.config.log_text(&format!("🛡️ [Methylation] Stripped {} suppressed path(s) under threshold {}: {}",
+                    tolerated.len(), self.config.violation_threshold, self.config.report(&tolerated.join(", ")))));
+            }
+            if !scrubbed.is_empty() {
+                info!("{}", self.config.log_text(&format!("⚔️ [Immunity] Scrubbed {} path(s) outside the Allow Map: {}",
+                    scrubbed.len(), self.config.report(&scrubbed.join(", ")))));
+            }
+        }
+
+        Ok(Action::Continue)
+    }
+}
+
+impl HttpContext for LeukocyteFilter {
+
+    fn on_http_request_headers(&mut self, _num_headers: usize, end_of_stream: bool) -> Action {
+        self.identify_request();
+        if let Some(action) = self.serve_recent_blocks() {
+            return action;
+        }
+        if self.config.is_self_test(|name| self.get_http_request_header(name)) {
+            let report = self.config.self_test_report();
+            return self.send_local_reply(200, vec![("content-type", "application/json")], Some(&report));
+        }
+        match self.evaluate_request_headers(end_of_stream) {
+            Ok(()) => {
+                // Body-less decisions are final here, unless gRPC trailers are still to be checked
+                let trailers_pending = !end_of_stream && self.is_grpc() && self.config.grpc_mode == GrpcMode::HeadersAndTrailers;
+                if (!self.inspect_body || end_of_stream) && !trailers_pending {
+                    self.log_summary(None);
+                    // Headers are only still mutable in this phase; later decisions show on the response
+                    if let Some(outcome) = self.summary.decision_header(&self.config) {
+                        self.set_http_request_header(DECISION_HEADER, Some(outcome));
+                    }
+                }
+                self.config.headers_action(self.inspect_body && !end_of_stream, self.expects_continue)
+            }
+            Err(block) => {
+                let action = self.send_denial(&block);
+                self.log_summary(Some(&block));
+                action
+            }
+        }
+    }
+
+    fn on_http_request_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
+        // The clock is only read when the histogram is enabled.
+        let started = (self.inspect_body && self.config.inspection_latency_metric)
+            .then(|| self.get_current_time());
+        let result = self.evaluate_request_body(body_size, end_of_stream);
+        if !matches!(result, Ok(Action::Pause)) {
+            if let Some((id, elapsed)) = started.and_then(|t| self.metrics.inspection_sample(t, self.get_current_time())) {
+                let _ = proxy_wasm::hostcalls::record_metric(id, elapsed);
+            }
+        }
+        match result {
+            Ok(Action::Pause) => Action::Pause,
+            Ok(action) => {
+                self.log_summary(None);
+                action
+            }
+            Err(block) => {
+                let action = self.send_denial(&block);
+                self.log_summary(Some(&block));
+                action
+            }
+        }
+    }
+
+    fn on_http_request_trailers(&mut self, _num_trailers: usize) -> Action {
+        if self.is_grpc() && self.config.grpc_mode == GrpcMode::HeadersAndTrailers {
+            let trailers = self.get_http_request_trailers();
+            if let Err(block) = self.config.inspect_headers(&trailers) {
+                let action = self.send_denial(&block);
+                self.log_summary(Some(&block));
+                return action;
+            }
+            self.log_summary(None);
+        }
+        Action::Continue
+    }
+
+    fn on_http_response_headers(&mut self, _num_headers: usize, end_of_stream: bool) -> Action {
+        if let Some(outcome) = self.summary.decision_header(&self.config) {
+            self.set_http_response_header(DECISION_HEADER, Some(outcome));
+        }
+        let status = self.get_http_response_header(":status").and_then(|s| s.parse().ok());
+        if !self.config.redacts_status(status) || end_of_stream {
+            return Action::Continue;
+        }
+        let is_json = self.get_http_response_header("content-type").is_some_and(|ct| is_json_content_type(&ct));
+        let encoding = self.get_http_response_header("content-encoding");
+        self.response_encoding = ContentEncoding::parse(encoding.as_deref()).filter(|_| is_json);
+        if self.response_encoding.is_some() {
+            // The redacted body has a different length
+            self.set_http_response_header("content-length", None);
+        }
+        Action::Continue
+    }
+
+    fn on_http_response_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
+        let Some(encoding) = self.response_encoding else { return Action::Continue };
+        if !end_of_stream {
+            return Action::Pause;
+        }
+        let body = self.get_http_response_body(0, body_size).unwrap_or_default();
+        if let Some(redacted) = self.config.redact_response(&body, encoding) {
+            self.set_http_response_body(0, body_size, &redacted);
+        }
+        Action::Continue
+    }
+
+    fn on_log(&mut self) {
+        increment(self.metrics.outcome(self.passthrough));
+        if let Some(line) = self.summary.decision_log_line(&self.config, &self.request) {
+            info!("{}", line);
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helper: Content-Type Gate
+// -----------------------------------------------------------------------------
+/// Matches `application/json` and structured-syntax-suffix types such as
+/// `application/vnd.api+json` or `application/merge-patch+json` (RFC 6839).
+fn is_json_content_type(content_type: &str) -> bool {
+    let media_type = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+    match media_type.split_once('/') {
+        Some(("application", "json")) => true,
+        Some(("application", subtype)) => subtype.ends_with("+json"),
+        _ => false,
+    }
+}
+
+fn is_form_content_type(content_type: &str) -> bool {
+    content_type.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case("application/x-www-form-urlencoded")
+}
+
+fn is_csv_content_type(content_type: &str) -> bool {
+    content_type.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case("text/csv")
+}
+
+fn is_multipart_content_type(content_type: &str) -> bool {
+    content_type.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case("multipart/form-data")
+}
+
+/// Matches `application/grpc` and its `+proto`/`+json` variants (not gRPC-Web).
+fn is_grpc_content_type(content_type: &str) -> bool {
+    let media_type = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+    media_type == "application/grpc" || media_type.starts_with("application/grpc+")
+}
+
+/// `Expect: 100-continue`: the body follows only after an interim response.
+fn expects_continue(headers: &[(String, String)]) -> bool {
+    headers.iter().any(|(k, v)| k.eq_ignore_ascii_case("expect") && v.trim().eq_ignore_ascii_case("100-continue"))
+}
+
+/// WebSocket frames are not request bodies the filter can parse.
+fn is_websocket_upgrade(headers: &[(String, String)]) -> bool {
+    headers.iter().any(|(k, v)| k.eq_ignore_ascii_case("upgrade") && v.trim().eq_ignore_ascii_case("websocket"))
+}
+
+/// Matches `application/grpc-web`, `application/grpc-web-text` and their `+proto` forms.
+fn is_grpc_web_content_type(content_type: &str) -> bool {
+    content_type.split(';').next().unwrap_or("").trim().to_lowercase().starts_with("application/grpc-web")
+}
+
+fn is_grpc_web_text(content_type: &str) -> bool {
+    content_type.split(';').next().unwrap_or("").trim().to_lowercase().starts_with("application/grpc-web-text")
+}
+
+/// Whether a block must be reported via grpc-status. gRPC-Web clients read the
+/// same status headers, so they are included here.
+fn expects_grpc_reply(content_type: &str) -> bool {
+    content_type.trim().to_lowercase().starts_with("application/grpc")
+}
+
+// -----------------------------------------------------------------------------
+// Helper: URL Decoding
+// -----------------------------------------------------------------------------
+/// Decodes `%XX` escapes once. Malformed escapes are kept literally.
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Decodes `%xx`, collapses duplicate slashes and resolves `.`/`..` segments.
+/// `..` never climbs above the root.
+fn normalize_path(path: &str) -> String {
+    let decoded = percent_decode(path);
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            s => segments.push(s),
+        }
+    }
+    let mut normalized = format!("/{}", segments.join("/"));
+    if decoded.ends_with('/') && !segments.is_empty() {
+        normalized.push('/');
+    }
+    normalized
+}
+
+/// True when decoding twice yields something different from decoding once.
+/// Deliberately bounded at two passes.
+fn is_double_encoded(input: &str) -> bool {
+    let once = percent_decode(input);
+    percent_decode(&once) != once
+}
+
+// -----------------------------------------------------------------------------
+// Helper: Client Address
+// -----------------------------------------------------------------------------
+/// The lowercased `scheme://authority` of a Referer URL, if it has one.
+fn referer_origin(referer: &str) -> Option<String> {
+    let (scheme, rest) = referer.trim().split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+    if scheme.is_empty() || authority.is_empty() {
+        return None;
+    }
+    Some(format!("{}://{}", scheme, authority).to_lowercase())
+}
+
+/// Strips the port from `ip:port` / `[v6]:port` source addresses.
+fn client_ip(address: &str) -> &str {
+    if let Some(rest) = address.strip_prefix('[') {
+        return rest.split(']').next().unwrap_or(rest);
+    }
+    match address.rsplit_once(':') {
+        Some((ip, port)) if !ip.contains(':') && port.parse::<u16>().is_ok() => ip,
+        _ => address,
+    }
+}
+
+/// The name in a Host/`:authority` value, without port or trailing dot.
+fn host_name(authority: &str) -> &str {
+    let name = client_ip(authority.trim());
+    name.strip_suffix('.').unwrap_or(name)
+}
+
+/// An IPv4 or IPv6 network in CIDR notation; a bare address is a host route.
+#[derive(Debug, Clone, PartialEq)]
+struct Cidr {
+    network: std::net::IpAddr,
+    prefix: u32,
+}
+
+impl Cidr {
+    fn parse(text: &str) -> Option<Self> {
+        let (addr, prefix) = match text.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix.parse::<u32>().ok()?)),
+            None => (text.trim(), None),
+        };
+        let network: std::net::IpAddr = addr.parse().ok()?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        (prefix <= max).then_some(Cidr { network, prefix })
+    }
+
+    fn contains(&self, ip: &std::net::IpAddr) -> bool {
+        use std::net::IpAddr::{V4, V6};
+        match (self.network, ip) {
+            (V4(net), V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix).unwrap_or(0);
+                u32::from(net) & mask == u32::from(*ip) & mask
+            }
+            (V6(net), V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix).unwrap_or(0);
+                u128::from(net) & mask == u128::from(*ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helper: TLS Versions
+// -----------------------------------------------------------------------------
+/// Orders `SSLv3` < `TLSv1` < `TLSv1.1` < `TLSv1.2` < `TLSv1.3`.
+fn tls_version_rank(version: &str) -> Option<(u8, u8)> {
+    match version.trim() {
+        "SSLv3" => Some((0, 3)),
+        v => {
+            let rest = v.strip_prefix("TLSv")?;
+            let (major, minor) = rest.split_once('.').unwrap_or((rest, "0"));
+            Some((major.parse().ok()?, minor.parse().ok()?))
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helper: Authorization Scheme
+// -----------------------------------------------------------------------------
+/// Extracts the lowercased auth scheme (`Bearer abc` -> `bearer`). Returns `None`
+/// for empty values or schemes that are not a valid RFC 7230 token.
+fn auth_scheme(value: &str) -> Option<String> {
+    let scheme = value.trim().split_ascii_whitespace().next()?;
+    let is_tchar = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    if scheme.chars().all(is_tchar) {
+        Some(scheme.to_lowercase())
+    } else {
+        None
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helper: Serialized Size
+// -----------------------------------------------------------------------------
+/// Length of the compact serialization of `value`, computed without allocating it.
+fn serialized_len(value: &serde_json::Value) -> usize {
+    struct Counter(usize);
+    impl std::io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    let _ = serde_json::to_writer(&mut counter, value);
+    counter.0
+}
+
+// -----------------------------------------------------------------------------
+// Helper: Array Depth
+// -----------------------------------------------------------------------------
+/// The most arrays found on any root-to-leaf path, walked iteratively so a
+/// hostile document cannot exhaust the stack here.
+fn array_depth(value: &serde_json::Value) -> usize {
+    let mut deepest = 0;
+    let mut stack = vec![(value, 0)];
+    while let Some((v, depth)) = stack.pop() {
+        match v {
+            serde_json::Value::Array(arr) => {
+                deepest = deepest.max(depth + 1);
+                stack.extend(arr.iter().map(|child| (child, depth + 1)));
+            }
+            serde_json::Value::Object(map) => stack.extend(map.values().map(|child| (child, depth))),
+            _ => {}
+        }
+    }
+    deepest
+}
+
+/// Whether the document holds more than `limit` string values. Stops
+/// counting as soon as the limit is passed.
+fn count_strings_beyond(value: &serde_json::Value, limit: usize) -> bool {
+    let mut count = 0;
+    let mut stack = vec![value];
+    while let Some(v) = stack.pop() {
+        match v {
+            serde_json::Value::String(_) => {
+                count += 1;
+                if count > limit {
+                    return true;
+                }
+            }
+            serde_json::Value::Array(arr) => stack.extend(arr),
+            serde_json::Value::Object(map) => stack.extend(map.values()),
+            _ => {}
+        }
+    }
+    false
+}
+
+// -----------------------------------------------------------------------------
+// Helper: Path Stripping
+// -----------------------------------------------------------------------------
+/// Whether `path` is one of `roots` or lies beneath one.
+fn is_within_any(path: &str, roots: &[String]) -> bool {
+    roots.iter().any(|root| path == root
+        || path.strip_prefix(root.as_str()).is_some_and(|rest| rest.starts_with('.')))
+}
+
+/// The document body rules run on: the body with `query` added at the top
+/// level. A body that already has a `query` key keeps it beside the params,
+/// as an array, so neither hides the other; a body that is not an object is
+/// paired with the params the same way. Query params are inspected only,
+/// never rewritten, so tolerated `query.` paths are not stripped.
+fn with_query<'a>(body: Option<&'a serde_json::Value>, query: Option<&serde_json::Value>) -> Option<Cow<'a, serde_json::Value>> {
+    let Some(query) = query else { return body.map(Cow::Borrowed) };
+    let merged = match body {
+        None => serde_json::json!({ "query": query }),
+        Some(serde_json::Value::Object(map)) => {
+            let mut map = map.clone();
+            let params = match map.remove("query") {
+                Some(existing) => serde_json::Value::Array(vec![existing, query.clone()]),
+                None => query.clone(),
+            };
+            map.insert("query".to_string(), params);
+            serde_json::Value::Object(map)
+        }
+        Some(other) => serde_json::json!([other, { "query": query }]),
+    };
+    Some(Cow::Owned(merged))
+}
+
+/// Removes every key whose flattened path is in `paths`, using the same
+/// array-transparent addressing as `flatten_json`.
+fn strip_paths(value: &mut serde_json::Value, prefix: &str, paths: &[String]) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|k, v| {
+                let path = if prefix.is_empty() { k.clone() } else { format!("{}.{}", prefix, k) };
+                if paths.contains(&path) {
+                    return false;
+                }
+                strip_paths(v, &path, paths);
+                true
+            });
+        }
+        serde_json::Value::Array(arr) => arr.iter_mut().for_each(|v| strip_paths(v, prefix, paths)),
+        _ => {}
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helper: Flatten JSON (The transcription process)
+// -----------------------------------------------------------------------------
+fn flatten_json(value: &serde_json::Value, prefix: &str) -> Vec<String> {
+    let mut paths = Vec::new();
+    
+    match value {
+        serde_json::Value::Object(map) => {
+            for (k, v) in map {
+                let new_key = if prefix.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{}.{}", prefix, k)
+                };
+                paths.push(new_key.clone());
+                paths.extend(flatten_json(v, &new_key));
+            }
+        }
+        serde_json::Value::Array(arr) => {
+             // Treat array indices as separate paths? Or ignore?
+             // Common practice: flatten with [i] or just recurse.
+             // Simpler for this demo: just recurse into objects
+             for v in arr {
+                 paths.extend(flatten_json(v, prefix));
+             }
+        }
+        _ => {}
+    }
+    paths
+}
+
+// -----------------------------------------------------------------------------
+// Helper: Streaming Key Scan
+// -----------------------------------------------------------------------------
+/// Emits the dotted path of every object key fully present in a (possibly
+/// truncated) JSON prefix, using the same array-transparent naming as
+/// `flatten_json`. Scanning stops quietly at the first incomplete token.
+fn scan_prefix_paths(prefix: &[u8]) -> Vec<String> {
+    enum Frame {
+        Object { key: Option<String>, expect_key: bool },
+        Array,
+    }
+
+    let mut paths = Vec::new();
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut i = 0;
+
+    while i < prefix.len() {
+        match prefix[i] {
+            b'{' => stack.push(Frame::Object { key: None, expect_key: true }),
+            b'[' => stack.push(Frame::Array),
+            b'}' | b']' => {
+                stack.pop();
+            }
+            b',' => {
+                if let Some(Frame::Object { expect_key, .. }) = stack.last_mut() {
+                    *expect_key = true;
+                }
+            }
+            b'"' => {
+                let Some((text, end)) = scan_string(prefix, i + 1) else { break };
+                i = end;
+                if let Some(Frame::Object { key, expect_key: expect @ true }) = stack.last_mut() {
+                    *key = Some(text);
+                    *expect = false;
+                    let segments: Vec<&str> = stack.iter()
+                        .filter_map(|f| match f {
+                            Frame::Object { key: Some(k), .. } => Some(k.as_str()),
+                            _ => None,
+                        })
+                        .collect();
+                    paths.push(segments.join("."));
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    paths
+}
+
+/// Decodes a JSON string starting just after its opening quote. Returns the text
+/// and the index of the closing quote, or `None` if the string is truncated.
+fn scan_string(bytes: &[u8], start: usize) -> Option<(String, usize)> {
+    let mut raw = Vec::new();
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => return Some((String::from_utf8_lossy(&raw).into_owned(), i)),
+            b'\\' => {
+                let escaped = *bytes.get(i + 1)?;
+                match escaped {
+                    b'u' => {
+                        let hex = std::str::from_utf8(bytes.get(i + 2..i + 6)?).ok()?;
+                        let c = u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+                            .unwrap_or(char::REPLACEMENT_CHARACTER);
+                        raw.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                        i += 4;
+                    }
+                    b'n' => raw.push(b'\n'),
+                    b't' => raw.push(b'\t'),
+                    b'r' => raw.push(b'\r'),
+                    b'b' => raw.push(0x08),
+                    b'f' => raw.push(0x0c),
+                    other => raw.push(other),
+                }
+                i += 2;
+            }
+            b => {
+                raw.push(b);
+                i += 1;
+            }
+        }
+    }
+    None
+}
+
+// -----------------------------------------------------------------------------
+// Helper: gRPC-Web Framing
+// -----------------------------------------------------------------------------
+/// Frame flag marking a trailers (metadata) frame rather than a message.
+const GRPC_WEB_TRAILER_FLAG: u8 = 0x80;
+
+/// Decodes a grpc-web-text body. Streamed bodies may be several padded base64
+/// chunks back to back, so each padded run is decoded separately.
+fn decode_grpc_web_text(body: &[u8]) -> Option<Vec<u8>> {
+    use base64::Engine;
+    let text: Vec<u8> = body.iter().copied().filter(|b| !b.is_ascii_whitespace()).collect();
+    let mut decoded = Vec::new();
+    let mut start = 0;
+    while start < text.len() {
+        let mut end = start;
+        while end < text.len() && text[end] != b'=' {
+            end += 1;
+        }
+        while end < text.len() && text[end] == b'=' {
+            end += 1;
+        }
+        decoded.extend(base64::engine::general_purpose::STANDARD.decode(&text[start..end]).ok()?);
+        start = end;
+    }
+    Some(decoded)
+}
+
+/// `(flags, payload)` for each complete 5-byte-prefixed frame; a truncated
+/// trailing frame is ignored.
+fn grpc_web_frames(mut data: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut frames = Vec::new();
+    while data.len() >= 5 {
+        let len = u32::from_be_bytes([data[1], data[2], data[3], data[4]]) as usize;
+        let Some(payload) = data.get(5..5 + len) else { break };
+        frames.push((data[0], payload));
+        data = &data[5 + len..];
+    }
+    frames
+}
+
+/// Trailer frames carry HTTP/1-style `name: value` lines; names are lowercased.
+fn parse_grpc_web_metadata(payload: &[u8]) -> Vec<(String, String)> {
+    String::from_utf8_lossy(payload).lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+        .collect()
+}
+
+// -----------------------------------------------------------------------------
+// Helper: Multipart
+// -----------------------------------------------------------------------------
+/// One `multipart/form-data` part (RFC 7578), borrowing its bytes from the body.
+#[derive(Debug, PartialEq)]
+struct MultipartPart<'a> {
+    name: Option<String>,
+    filename: Option<String>,
+    content_type: Option<String>,
+    data: &'a [u8],
+}
+
+impl MultipartPart<'_> {
+    /// Uploads and non-text parts are treated as opaque files.
+    fn is_file(&self) -> bool {
+        self.filename.is_some()
+            || self.content_type.as_deref().is_some_and(|ct| !ct.trim().to_lowercase().starts_with("text/"))
+    }
+}
+
+/// The `boundary` parameter of a multipart content-type, unquoted.
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    content_type.split(';').skip(1)
+        .filter_map(|param| param.split_once('='))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim().trim_matches('"').to_string())
+        .filter(|boundary| !boundary.is_empty())
+}
+
+/// Splits a multipart body into parts. The preamble and epilogue are ignored;
+/// a part without a header/body separator is skipped.
+fn parse_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<MultipartPart<'a>> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let Some(first) = find_bytes(body, delimiter.as_bytes()) else { return parts };
+    let mut rest = &body[first + delimiter.len()..];
+
+    // Each section runs from the end of one delimiter to the start of the next
+    while !rest.starts_with(b"--") {
+        let end = find_bytes(rest, delimiter.as_bytes()).unwrap_or(rest.len());
+        let section = &rest[..end];
+        let section = section.strip_prefix(b"\r\n").or_else(|| section.strip_prefix(b"\n")).unwrap_or(section);
+        if let Some((head, data)) = split_part(section) {
+            let data = data.strip_suffix(b"\r\n").or_else(|| data.strip_suffix(b"\n")).unwrap_or(data);
+            parts.push(part_from_headers(&String::from_utf8_lossy(head), data));
+        }
+        if end == rest.len() {
+            break;
+        }
+        rest = &rest[end + delimiter.len()..];
+    }
+    parts
+}
+
+fn split_part(section: &[u8]) -> Option<(&[u8], &[u8])> {
+    if let Some(i) = find_bytes(section, b"\r\n\r\n") {
+        return Some((&section[..i], &section[i + 4..]));
+    }
+    find_bytes(section, b"\n\n").map(|i| (&section[..i], &section[i + 2..]))
+}
+
+fn part_from_headers<'a>(head: &str, data: &'a [u8]) -> MultipartPart<'a> {
+    let mut part = MultipartPart { name: None, filename: None, content_type: None, data };
+    for line in head.lines() {
+        let Some((header, value)) = line.split_once(':') else { continue };
+        if header.trim().eq_ignore_ascii_case("content-type") {
+            part.content_type = Some(value.trim().to_string());
+        } else if header.trim().eq_ignore_ascii_case("content-disposition") {
+            for param in value.split(';').skip(1) {
+                let Some((key, v)) = param.split_once('=') else { continue };
+                let v = v.trim().trim_matches('"').to_string();
+                match key.trim().to_lowercase().as_str() {
+                    "name" => part.name = Some(v),
+                    "filename" => part.filename = Some(v),
+                    _ => {}
+                }
+            }
+        }
+    }
+    part
+}
+
+/// Header values grouped under their lowercased name, in order of first
+/// appearance; repeated headers keep every occurrence, in order.
+fn group_headers(headers: &[(String, String)]) -> Vec<(String, Vec<&str>)> {
+    let mut groups: Vec<(String, Vec<&str>)> = Vec::new();
+    for (name, value) in headers {
+        let name = name.to_lowercase();
+        match groups.iter_mut().find(|(existing, _)| *existing == name) {
+            Some((_, values)) => values.push(value),
+            None => groups.push((name, vec![value])),
+        }
+    }
+    groups
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+// -----------------------------------------------------------------------------
+// Helper: CSV
+// -----------------------------------------------------------------------------
+/// Minimal RFC 4180 reader: quoted fields, `""` escapes, embedded commas and
+/// newlines, LF or CRLF record endings.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, in_quotes) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', _) => in_quotes = !in_quotes,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\r', false) if chars.peek() == Some(&'\n') => {}
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (c, _) => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+// -----------------------------------------------------------------------------
+// Helper: Patterns
+// -----------------------------------------------------------------------------
+fn compile_patterns(patterns: &[String], errors: &mut Vec<String>) -> Vec<Regex> {
+    patterns.iter()
+        .filter_map(|p| Regex::new(p).map_err(|e| errors.push(format!("{}: {}", p, e))).ok())
+        .collect()
+}
+
+/// Anchored regex for a path glob: `**` spans segments, `*` and `?` stay
+/// within one.
+fn glob_to_regex(glob: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^{}$", glob_pattern(glob, '/')))
+}
+
+/// `glob_to_regex` for dotted JSON paths. A trailing `.**` also matches the
+/// parent itself, since flattening reports `config.public` as well as its
+/// children.
+fn path_glob_to_regex(glob: &str) -> Result<Regex, regex::Error> {
+    match glob.strip_suffix(".**") {
+        Some(root) => Regex::new(&format!("^{}(?:\\..*)?$", glob_pattern(root, '.'))),
+        None => Regex::new(&format!("^{}$", glob_pattern(glob, '.'))),
+    }
+}
+
+fn glob_pattern(glob: &str, separator: char) -> String {
+    let segment = format!("[^{}]", regex::escape(&separator.to_string()));
+    let mut pattern = String::new();
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                pattern.push_str(".*");
+            }
+            '*' => pattern.push_str(&format!("{}*", segment)),
+            '?' => pattern.push_str(&segment),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern
+}
+
+/// All string leaves of a JSON document.
+fn collect_strings<'a>(value: &'a serde_json::Value, out: &mut Vec<&'a str>) {
+    match value {
+        serde_json::Value::String(s) => out.push(s),
+        serde_json::Value::Array(arr) => arr.iter().for_each(|v| collect_strings(v, out)),
+        serde_json::Value::Object(map) => map.values().for_each(|v| collect_strings(v, out)),
+        _ => {}
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helper: Parse Ambiguity
+// -----------------------------------------------------------------------------
+/// Largest integer every JSON parser represents exactly (2^53 - 1).
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// Re-reads already valid JSON, failing on the first object with a repeated
+/// key; `serde_json::Value` silently keeps the last one.
+fn duplicate_key(text: &str) -> Option<String> {
+    serde_json::from_str::<DuplicateKeyCheck>(text).err().map(|e| e.to_string())
+}
+
+struct DuplicateKeyCheck;
+
+impl<'de> Deserialize<'de> for DuplicateKeyCheck {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(DuplicateKeyCheck)
+    }
+}
+
+impl<'de> serde::de::Visitor<'de> for DuplicateKeyCheck {
+    type Value = DuplicateKeyCheck;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<Self, E> { Ok(self) }
+    fn visit_i64<E>(self, _: i64) -> Result<Self, E> { Ok(self) }
+    fn visit_u64<E>(self, _: u64) -> Result<Self, E> { Ok(self) }
+    fn visit_f64<E>(self, _: f64) -> Result<Self, E> { Ok(self) }
+    fn visit_str<E>(self, _: &str) -> Result<Self, E> { Ok(self) }
+    fn visit_unit<E>(self) -> Result<Self, E> { Ok(self) }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self, A::Error> {
+        while seq.next_element::<DuplicateKeyCheck>()?.is_some() {}
+        Ok(self)
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Self, A::Error> {
+        let mut seen = HashSet::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if !seen.insert(key.clone()) {
+                return Err(serde::de::Error::custom(format!("duplicate key {:?}", key)));
+            }
+            map.next_value::<DuplicateKeyCheck>()?;
+        }
+        Ok(self)
+    }
+}
+
+fn has_unsafe_number(value: &serde_json::Value) -> bool {
+    let mut stack = vec![value];
+    while let Some(v) = stack.pop() {
+        match v {
+            serde_json::Value::Number(n) => {
+                let unsafe_number = match (n.as_u64(), n.as_i64()) {
+                    (Some(u), _) => u > MAX_SAFE_INTEGER,
+                    (None, Some(i)) => i.unsigned_abs() > MAX_SAFE_INTEGER,
+                    // Floats: integer literals too large for u64/i64 land here
+                    _ => n.as_f64().is_some_and(|f| f.fract() == 0.0 && f.abs() > MAX_SAFE_INTEGER as f64),
+                };
+                if unsafe_number {
+                    return true;
+                }
+            }
+            serde_json::Value::Array(arr) => stack.extend(arr),
+            serde_json::Value::Object(map) => stack.extend(map.values()),
+            _ => {}
+        }
+    }
+    false
+}
+
+fn has_control_chars(value: &serde_json::Value) -> bool {
+    let is_bad = |s: &str| s.chars().any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r'));
+    let mut stack = vec![value];
+    while let Some(v) = stack.pop() {
+        match v {
+            serde_json::Value::String(s) if is_bad(s) => return true,
+            serde_json::Value::Array(arr) => stack.extend(arr),
+            serde_json::Value::Object(map) => {
+                if map.keys().any(|k| is_bad(k)) {
+                    return true;
+                }
+                stack.extend(map.values());
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+// -----------------------------------------------------------------------------
+// Helper: Log Snippets
+// -----------------------------------------------------------------------------
+/// Truncates to `max_len` characters and masks the middle half, keeping only a
+/// quarter at each end (`administrator`, 8 -> `ad****st`).
+fn mask_snippet(value: &str, max_len: usize) -> String {
+    let chars: Vec<char> = value.chars().take(max_len).collect();
+    let keep = chars.len() / 4;
+    chars.iter().enumerate()
+        .map(|(i, c)| if i < keep || i >= chars.len() - keep { *c } else { '*' })
+        .collect()
+}
+
+// -----------------------------------------------------------------------------
+// Helper: Body Hashing
+// -----------------------------------------------------------------------------
+/// Lowercase hex SHA-256, the form hash feeds are published in.
+fn sha256_hex(bytes: &[u8]) -> String {
+    use sha2::Digest;
+    sha2::Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// -----------------------------------------------------------------------------
+// Helper: Binary Policy Encoding
+// -----------------------------------------------------------------------------
+/// Marks a compact binary policy instead of JSON (format version 1).
+const BINARY_POLICY_MAGIC: &[u8] = b"LKCY\x01";
+
+/// Sections of a binary policy, each `tag: u8, count: u32 LE`, then `count`
+/// strings of `len: u32 LE` + UTF-8. `Settings` holds one string: a JSON
+/// object with every other key. Path sets skip JSON parsing entirely.
+const BINARY_SECTIONS: [(u8, &str); 4] = [
+    (0, ""), // Settings
+    (1, "suppression_paths"),
+    (2, "allow_paths"),
+    (3, "deny_key_names"),
+];
+
+fn decode_binary_policy(mut bytes: &[u8]) -> Result<serde_json::Value, String> {
+    fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], String> {
+        if bytes.len() < n {
+            return Err("truncated binary policy".to_string());
+        }
+        let (head, rest) = bytes.split_at(n);
+        *bytes = rest;
+        Ok(head)
+    }
+    fn take_u32(bytes: &mut &[u8]) -> Result<usize, String> {
+        Ok(u32::from_le_bytes(take(bytes, 4)?.try_into().unwrap()) as usize)
+    }
+
+    let mut document = serde_json::Map::new();
+    while !bytes.is_empty() {
+        let tag = take(&mut bytes, 1)?[0];
+        let Some(&(_, key)) = BINARY_SECTIONS.iter().find(|(t, _)| *t == tag) else {
+            return Err(format!("unknown binary policy section {}", tag));
+        };
+        let count = take_u32(&mut bytes)?;
+        let mut strings = Vec::with_capacity(count.min(bytes.len() / 4));
+        for _ in 0..count {
+            let len = take_u32(&mut bytes)?;
+            let text = std::str::from_utf8(take(&mut bytes, len)?).map_err(|e| e.to_string())?;
+            strings.push(text);
+        }
+
+        if key.is_empty() {
+            for settings in strings {
+                let serde_json::Value::Object(map) = serde_json::from_str(settings).map_err(|e| e.to_string())? else {
+                    return Err("binary policy settings must be a JSON object".to_string());
+                };
+                document.extend(map);
+            }
+        } else {
+            document.insert(key.to_string(), strings.into_iter().map(serde_json::Value::from).collect());
+        }
+    }
+    Ok(serde_json::Value::Object(document))
+}
+
+// -----------------------------------------------------------------------------
+// Helper: Response Redaction
+// -----------------------------------------------------------------------------
+/// Marker replacing redacted response values.
+const REDACTED: &str = "[REDACTED]";
+
+/// Replaces every value at `path` (descending through arrays) with
+/// `REDACTED`, returning how many were replaced.
+fn redact_at_path(value: &mut serde_json::Value, path: &[&str]) -> usize {
+    let Some((segment, rest)) = path.split_first() else { return 0 };
+    match value {
+        serde_json::Value::Object(map) => match map.get_mut(*segment) {
+            Some(found) if rest.is_empty() => {
+                *found = REDACTED.into();
+                1
+            }
+            Some(found) => redact_at_path(found, rest),
+            None => 0,
+        },
+        serde_json::Value::Array(arr) => arr.iter_mut().map(|v| redact_at_path(v, path)).sum(),
+        _ => 0,
+    }
+}
+
+/// Decompresses at most `limit` bytes; a larger body yields `None`.
+fn gunzip(body: &[u8], limit: usize) -> Option<Vec<u8>> {
+    use std::io::Read;
+    let mut out = Vec::new();
+    flate2::read::GzDecoder::new(body).take(limit as u64 + 1).read_to_end(&mut out).ok()?;
+    (out.len() <= limit).then_some(out)
+}
+
+fn gzip(body: &[u8]) -> Option<Vec<u8>> {
+    use std::io::Write;
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(body).ok()?;
+    encoder.finish().ok()
+}
+
+/// A rewritten request body in the encoding it arrived in. A body that cannot
+/// be re-compressed is blocked: forwarding it empty or unstripped is not an option.
+fn recompress(encoded: Vec<u8>, gzipped: bool, compress: impl FnOnce(&[u8]) -> Option<Vec<u8>>) -> Result<Vec<u8>, Block> {
+    if !gzipped {
+        return Ok(encoded);
+    }
+    compress(&encoded).ok_or_else(|| Block::new("Service Unavailable: Body Rewrite Failed", "rewrite-failed",
+        "🛡️ [Methylation] Stripped body could not be re-compressed".to_string()))
+}
+
+// -----------------------------------------------------------------------------
+// Helper: Path Resolution
+// -----------------------------------------------------------------------------
+/// Resolves a dotted path, descending through arrays the same way `flatten_json` does.
+fn values_at_path<'a>(value: &'a serde_json::Value, path: &str) -> Vec<&'a serde_json::Value> {
+    let mut current = vec![value];
+    for segment in path.split('.') {
+        let mut next = Vec::new();
+        while let Some(v) = current.pop() {
+            match v {
+                serde_json::Value::Object(map) => next.extend(map.get(segment)),
+                serde_json::Value::Array(arr) => current.extend(arr.iter()),
+                _ => {}
+            }
+        }
+        current = next;
+    }
+    current
+}
+
+/// Values found at a path with arrays expanded to their elements, matching
+/// how `flatten_json` reports array contents under the parent key.
+fn leaf_values(found: Vec<&serde_json::Value>) -> Vec<&serde_json::Value> {
+    let mut leaves = Vec::new();
+    let mut stack = found;
+    while let Some(v) = stack.pop() {
+        match v {
+            serde_json::Value::Array(arr) => stack.extend(arr.iter().rev()),
+            other => leaves.push(other),
+        }
+    }
+    leaves
+}
+
+/// Textual form of a string or number element, used for value comparisons.
+fn scalar_text(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Entry Point
+// -----------------------------------------------------------------------------
+#[cfg(feature = "plugin")]
+proxy_wasm::main! {{
+    proxy_wasm::set_log_level(LogLevel::Trace);
+    proxy_wasm::set_root_context(|_| -> Box<dyn RootContext> {
+        Box::new(LeukocyteRoot {
+            config: Rc::new(PolicyConfig::default()),
+            vm: VmConfig::default(),
+            metrics: None,
+            incident_queue: None,
+            config_queue: None,
+        })
+    });
+}}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_suffix_content_types_are_inspected() {
+        assert!(is_json_content_type("application/json"));
+        assert!(is_json_content_type("application/vnd.api+json"));
+        assert!(is_json_content_type("application/merge-patch+json; charset=utf-8"));
+        assert!(is_json_content_type("Application/JSON"));
+    }
+
+    #[test]
+    fn non_json_content_types_are_skipped() {
+        assert!(!is_json_content_type("application/octet-stream"));
+        assert!(!is_json_content_type("text/plain"));
+        assert!(!is_json_content_type("application/jsonp"));
+    }
+
+    #[test]
+    fn grpc_requests_bypass_json_body_parsing() {
+        let config = PolicyConfig::default();
+        assert_eq!(config.body_kind(Some("application/grpc")), None);
+        assert_eq!(config.body_kind(Some("application/grpc+json")), None);
+        assert_eq!(config.body_kind(Some("application/json")), Some(BodyKind::Json));
+        assert_eq!(config.body_kind(None), Some(BodyKind::Json));
+    }
+
+    #[test]
+    fn headers_only_mode_never_buffers_bodies() {
+        let config = PolicyConfig {
+            inspect_body: false,
+            allow_paths: ["id".to_string()].into(),
+            ..Default::default()
+        };
+        assert_eq!(config.body_kind(Some("application/json")), None);
+        assert_eq!(config.body_kind(None), None);
+    }
+
+    #[test]
+    fn body_only_mode_skips_header_suppression() {
+        let config = PolicyConfig {
+            inspect_headers: false,
+            suppression_paths: ["x-debug".to_string()].into(),
+            ..Default::default()
+        };
+        assert!(config.inspect_headers(&headers(&[("x-debug", "1")])).is_ok());
+        assert_eq!(config.body_kind(Some("application/json")), Some(BodyKind::Json));
+        assert_eq!(config.inspect_json(&json(r#"{"x-debug":1}"#)).unwrap_err().defense, "methylated");
+    }
+
+    #[test]
+    fn bypass_paths_match_exact_and_prefix() {
+        let config = PolicyConfig {
+            bypass_paths: ["/healthz".to_string(), "/metrics/*".to_string()].into(),
+            ..Default::default()
+        };
+        assert!(config.is_bypassed("/healthz"));
+        assert!(config.is_bypassed("/metrics/prometheus"));
+        assert!(!config.is_bypassed("/healthz/deep"));
+        assert!(!config.is_bypassed("/api"));
+        assert!(!PolicyConfig::default().is_bypassed("/healthz"));
+    }
+
+    #[test]
+    fn options_preflight_skips_body_handling() {
+        let config = PolicyConfig::default();
+        assert!(config.is_preflight_passthrough(Some("OPTIONS")));
+        assert!(!config.is_preflight_passthrough(Some("POST")));
+        assert!(!config.is_preflight_passthrough(None));
+
+        let strict = PolicyConfig { passthrough_options: false, ..Default::default() };
+        assert!(!strict.is_preflight_passthrough(Some("OPTIONS")));
+    }
+
+    fn headers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn auth_config() -> PolicyConfig {
+        PolicyConfig {
+            allowed_auth_schemes: ["bearer", "basic"].iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn allowed_bearer_scheme_passes() {
+        let config = auth_config();
+        assert!(config.inspect_headers(&headers(&[("authorization", "Bearer abc.def")])).is_ok());
+        assert!(config.inspect_headers(&headers(&[(":path", "/")])).is_ok());
+    }
+
+    #[test]
+    fn custom_or_malformed_scheme_is_rejected() {
+        let config = auth_config();
+        for value in ["Token abc", "", "Bea(rer) abc"] {
+            let block = config.inspect_headers(&headers(&[("authorization", value)])).unwrap_err();
+            assert_eq!(block.defense, "bad-auth-scheme");
+        }
+    }
+
+    #[test]
+    fn logical_json_size_is_bounded_after_parsing() {
+        let config = PolicyConfig { max_json_bytes: Some(64), ..Default::default() };
+        // A gzip body well under the limit on the wire that inflates past it
+        let plain = format!(r#"{{"a":"{}"}}"#, "x".repeat(200));
+        let wire = gzip(plain.as_bytes()).unwrap();
+        assert!(wire.len() < 64);
+        let decoded = config.decode_request_body(&wire, Some(ContentEncoding::Gzip));
+        let expanded = config.parse_json_body(&decoded).unwrap().unwrap();
+        assert_eq!(config.inspect_json(&expanded).unwrap_err().defense, "json-too-large");
+
+        // Exponent floats re-serialize longer than they were sent
+        let floats = br#"{"a":[1e15,1e15,1e15,1e15,1e15]}"#;
+        let expanded: serde_json::Value = serde_json::from_slice(floats).unwrap();
+        assert!(floats.len() < 64 && serialized_len(&expanded) > 64);
+        assert_eq!(config.inspect_json(&expanded).unwrap_err().defense, "json-too-large");
+
+        let small: serde_json::Value = serde_json::from_str(r#"{"a":1}"#).unwrap();
+        assert!(config.inspect_json(&small).is_ok());
+    }
+
+    fn json(text: &str) -> serde_json::Value {
+        serde_json::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn streaming_scan_catches_early_suppressed_field() {
+        let config = PolicyConfig {
+            suppression_paths: ["user.password".to_string()].into(),
+            streaming_inspection: true,
+            ..Default::default()
+        };
+        // The body is still arriving: the value and closing braces are missing.
+        let chunk = br#"{"items":[{"id":1}],"user":{"name":"a","password":"hun"#;
+        assert_eq!(config.inspect_partial(chunk).unwrap_err().defense, "methylated");
+        assert!(config.inspect_partial(br#"{"user":{"name":"a","pass"#).is_ok());
+
+        // Only JSON bodies are scanned early
+        assert!(BodyKind::Json.has_json_keys());
+        assert!(!BodyKind::Csv.has_json_keys() && !BodyKind::Form.has_json_keys());
+    }
+
+    #[test]
+    fn streaming_scan_matches_flattened_paths() {
+        let body = r#"{"a":{"b\u0022c":1,"d":[{"e":true}]},"f":"x,\"g\":1"}"#;
+        assert_eq!(scan_prefix_paths(body.as_bytes()), flatten_json(&json(body), ""));
+    }
+
+    fn sampling_decisions(config: &PolicyConfig, requests: u32) -> Vec<bool> {
+        (0..requests)
+            .map(|id| config.samples_out(Rng::for_context(config.rng_seed, id).next_f64()))
+            .collect()
+    }
+
+    fn inspected_count(config: &PolicyConfig, requests: u32) -> u64 {
+        sampling_decisions(config, requests).iter().filter(|out| !**out).count() as u64
+    }
+
+    #[test]
+    fn half_of_bodies_inspected_at_half_sample() {
+        let config = PolicyConfig { body_inspection_sample: 0.5, ..Default::default() };
+        let inspected = inspected_count(&config, 10_000);
+        assert!((4_500..5_500).contains(&inspected), "inspected {}", inspected);
+    }
+
+    #[test]
+    fn all_bodies_inspected_at_full_sample_or_strict_allow() {
+        assert_eq!(inspected_count(&PolicyConfig::default(), 1_000), 1_000);
+
+        let strict = PolicyConfig {
+            body_inspection_sample: 0.0,
+            allow_paths: ["id".to_string()].into(),
+            ..Default::default()
+        };
+        assert_eq!(inspected_count(&strict, 1_000), 1_000);
+    }
+
+    #[test]
+    fn same_seed_reproduces_sampling_decisions() {
+        let config: PolicyConfig = serde_json::from_str(r#"{"body_inspection_sample":0.5,"rng_seed":42}"#).unwrap();
+        assert_eq!(sampling_decisions(&config, 200), sampling_decisions(&config, 200));
+
+        let reseeded = PolicyConfig { rng_seed: 43, ..config.clone() };
+        assert_ne!(sampling_decisions(&config, 200), sampling_decisions(&reseeded, 200));
+    }
+
+    fn property_lookup<'a>(props: &'a [(&'a str, &'a str)]) -> impl Fn(&[&str]) -> Option<String> + 'a {
+        move |path| props.iter()
+            .find(|(k, _)| *k == path.join("."))
+            .map(|(_, v)| v.to_string())
+    }
+
+    #[test]
+    fn property_rules_allow_and_deny() {
+        let config: PolicyConfig = serde_json::from_str(r#"{"property_rules":[
+            {"path":"source.address","equals":"10.0.0.9:1234","action":"allow"},
+            {"path":"request.protocol","equals":"HTTP/1.0","action":"deny"},
+            {"path":"connection.tls_version","not_equals":"TLSv1.3","action":"deny"}
+        ]}"#).unwrap();
+
+        let modern = [("request.protocol", "HTTP/2"), ("connection.tls_version", "TLSv1.3")];
+        assert_eq!(config.inspect_properties(property_lookup(&modern)), Ok(false));
+
+        let legacy = [("request.protocol", "HTTP/1.0"), ("connection.tls_version", "TLSv1.3")];
+        let block = config.inspect_properties(property_lookup(&legacy)).unwrap_err();
+        assert_eq!((block.defense, block.rule.as_deref()), ("property-denied", Some("request.protocol")));
+
+        // Missing tls_version is "not equal", so plaintext is denied...
+        assert!(config.inspect_properties(property_lookup(&[("request.protocol", "HTTP/2")])).is_err());
+        // ...unless an earlier allow rule matched first.
+        let trusted = [("source.address", "10.0.0.9:1234"), ("request.protocol", "HTTP/1.0")];
+        assert_eq!(config.inspect_properties(property_lookup(&trusted)), Ok(true));
+    }
+
+    #[test]
+    fn default_allow_passes_unmatched_requests() {
+        assert!(PolicyConfig::default().check_default_action(false, false).is_ok());
+    }
+
+    #[test]
+    fn default_deny_blocks_unmatched_requests() {
+        let config: PolicyConfig = serde_json::from_str(r#"{"default_action":"deny"}"#).unwrap();
+        assert_eq!(config.check_default_action(false, false).unwrap_err().defense, "default-deny");
+        assert!(config.check_default_action(true, false).is_ok());
+        assert!(config.check_default_action(false, true).is_ok());
+    }
+
+    #[test]
+    fn old_tls_version_is_blocked() {
+        let config = PolicyConfig { min_tls_version: Some("TLSv1.2".into()), ..Default::default() };
+        for old in ["TLSv1.1", "TLSv1", "SSLv3", "garbage"] {
+            assert_eq!(config.inspect_tls(Some(old), None).unwrap_err().defense, "tls-version");
+        }
+    }
+
+    #[test]
+    fn current_tls_version_passes() {
+        let config = PolicyConfig {
+            min_tls_version: Some("TLSv1.2".into()),
+            sni_allow: ["api.example.com".to_string()].into(),
+            ..Default::default()
+        };
+        assert!(config.inspect_tls(Some("TLSv1.2"), Some("api.example.com")).is_ok());
+        assert!(config.inspect_tls(Some("TLSv1.3"), Some("api.example.com")).is_ok());
+        assert_eq!(config.inspect_tls(Some("TLSv1.3"), Some("other.example.com")).unwrap_err().defense, "sni-denied");
+    }
+
+    #[test]
+    fn plaintext_connections_are_explicit() {
+        let config = PolicyConfig { min_tls_version: Some("TLSv1.2".into()), ..Default::default() };
+        assert!(config.inspect_tls(None, None).is_ok());
+        let tls_only = PolicyConfig { allow_plaintext: false, ..config };
+        assert_eq!(tls_only.inspect_tls(Some(""), None).unwrap_err().defense, "tls-required");
+    }
+
+    #[test]
+    fn single_encoded_keys_are_not_flagged() {
+        let config = PolicyConfig { reject_double_encoding: true, ..Default::default() };
+        assert_eq!(percent_decode("to%6Ben"), "token");
+        assert!(config.inspect_uri("/api/items?to%6Ben=abc&q=a%20b").is_ok());
+        assert!(config.inspect_uri("/api/100%").is_ok());
+    }
+
+    #[test]
+    fn double_encoded_keys_are_blocked() {
+        let config = PolicyConfig { reject_double_encoding: true, ..Default::default() };
+        assert_eq!(config.inspect_uri("/api/items?to%256Ben=abc").unwrap_err().defense, "double-encoded");
+        assert_eq!(config.inspect_uri("/api/%252e%252e/admin").unwrap_err().defense, "double-encoded");
+        assert!(PolicyConfig::default().inspect_uri("/api/items?to%256Ben=abc").is_ok());
+    }
+
+    #[test]
+    fn traversal_and_plain_path_select_same_route() {
+        let config = PolicyConfig { normalize_path: true, ..Default::default() };
+        assert_eq!(config.route_for("/a/../admin"), config.route_for("/admin"));
+        assert_eq!(config.route_for("//admin/./users?id=1"), "/admin/users");
+        assert_eq!(config.route_for("/%61dmin/../../admin/"), "/admin/");
+
+        assert_eq!(PolicyConfig::default().route_for("/a/../admin?x=1"), "/a/../admin");
+    }
+
+    #[test]
+    fn retry_after_reflects_time_until_refill() {
+        let limit = RateLimit { requests_per_window: 2, window_secs: 60 };
+        let (state, retry) = limit.admit(None, 1_000);
+        assert_eq!((state, retry), (WindowState { window_start: 1_000, count: 1 }, None));
+        let (state, retry) = limit.admit(Some(state), 1_010);
+        assert_eq!(retry, None);
+        let (state, retry) = limit.admit(Some(state), 1_045);
+        assert_eq!(retry, Some(15));
+        assert_eq!(WindowState::decode(&state.encode()), Some(state));
+
+        // A fresh window starts once the old one has elapsed.
+        assert_eq!(limit.admit(Some(state), 1_060).1, None);
+    }
+
+    #[test]
+    fn rate_limit_block_renders_429_with_retry_after() {
+        let response = PolicyConfig::default()
+            .render_block(&Block::rate_limited(15, "10.0.0.1"), &RequestInfo::default(), false);
+        assert_eq!(response.status, 429);
+        assert!(response.headers.contains(&("retry-after".into(), "15".into())));
+        assert_eq!(client_ip("10.0.0.1:5555"), "10.0.0.1");
+        assert_eq!(client_ip("[::1]:443"), "::1");
+    }
+
+    #[test]
+    fn buffer_overflow_blocks_by_default() {
+        let config = PolicyConfig { buffer_cap_bytes: Some(1024), ..Default::default() };
+        assert_eq!(config.check_buffer(1024), Ok(true));
+        assert_eq!(config.check_buffer(1025).unwrap_err().defense, "buffer-overflow");
+    }
+
+    #[test]
+    fn buffer_overflow_can_pass_uninspected() {
+        let config = PolicyConfig {
+            buffer_cap_bytes: Some(1024),
+            on_buffer_overflow: OverflowAction::Pass,
+            ..Default::default()
+        };
+        assert_eq!(config.check_buffer(512), Ok(true));
+        assert_eq!(config.check_buffer(4096), Ok(false));
+    }
+
+    fn sample_block() -> Block {
+        Block::new("Access Denied: Pathogen Suppressed", "methylated", "matched user.password".into())
+    }
+
+    #[test]
+    fn deny_action_renders_forbidden() {
+        let response = PolicyConfig::default().render_block(&sample_block(), &RequestInfo::default(), false);
+        assert_eq!(response.status, 403);
+        assert_eq!(response.body.as_deref(), Some(&b"Access Denied: Pathogen Suppressed"[..]));
+    }
+
+    #[test]
+    fn strict_allow_block_reports_offending_path() {
+        let config = PolicyConfig {
+            allow_paths: ["id".to_string(), "profile".to_string(), "profile.name".to_string()].into(),
+            reveal_match: true,
+            ..Default::default()
+        };
+        let block = config.inspect_json(&json(r#"{"id":1,"profile":{"name":"a","ssn":"x"}}"#)).unwrap_err();
+        assert_eq!(block.rule.as_deref(), Some("profile.ssn"));
+        assert!(block.detail.contains("profile.ssn"));
+
+        let body = config.render_block(&block, &RequestInfo::default(), false).body.unwrap();
+        assert_eq!(body, b"Access Denied: Foreign Antigen (profile.ssn)");
+
+        let private = PolicyConfig { reveal_match: false, ..config };
+        let body = private.render_block(&block, &RequestInfo::default(), false).body.unwrap();
+        assert_eq!(body, b"Access Denied: Foreign Antigen");
+    }
+
+    #[test]
+    fn hidden_blocks_render_a_bare_not_found() {
+        let config = PolicyConfig::load(br#"{"suppression_paths": ["debug"], "block_as_not_found": true, "reveal_match": true}"#).unwrap();
+        let block = config.inspect_json(&json(r#"{"debug": 1}"#)).unwrap_err();
+
+        let response = config.render_block(&block, &RequestInfo::default(), false);
+        assert_eq!((response.status, response.headers, response.body), (404, vec![], None));
+
+        let grpc = config.render_block(&block, &RequestInfo::default(), true);
+        assert!(grpc.headers.contains(&("grpc-status".into(), "5".into())));
+        assert!(grpc.headers.iter().all(|(name, _)| name != "x-leukocyte-defense"));
+
+        // Path-segment suppression is hidden the same way
+        let segments = PolicyConfig::load(br#"{"suppression_path_segments": ["admin"], "block_as_not_found": true}"#).unwrap();
+        let block = segments.inspect_uri("/a/admin/b").unwrap_err();
+        let response = segments.render_block(&block, &RequestInfo::default(), false);
+        assert_eq!((response.status, response.headers, response.body), (404, vec![], None));
+
+        // Other defenses still identify themselves
+        let oversized = Block::new("Access Denied: JSON Payload Too Large", "json-too-large", String::new());
+        assert_eq!(config.render_block(&oversized, &RequestInfo::default(), false).status, 403);
+    }
+
+    #[test]
+    fn redirect_action_renders_location() {
+        let config: PolicyConfig = serde_json::from_str(
+            r#"{"block_action":{"type":"redirect","location":"/errors/blocked","status":303}}"#,
+        ).unwrap();
+        let response = config.render_block(&sample_block(), &RequestInfo::default(), false);
+        assert_eq!(response.status, 303);
+        assert!(response.headers.contains(&("location".into(), "/errors/blocked".into())));
+        assert_eq!(response.body, None);
+
+        let grpc = config.render_block(&sample_block(), &RequestInfo::default(), true);
+        assert_eq!(grpc.status, 200);
+    }
+
+    #[test]
+    fn block_log_includes_source_address() {
+        let request = RequestInfo {
+            source: Some("10.1.2.3:45678".into()),
+            method: Some("POST".into()),
+            path: Some("/api/users".into()),
+            ..Default::default()
+        };
+        let line = PolicyConfig::default().block_log_line(&sample_block(), &request);
+        assert!(line.contains("source=10.1.2.3:45678"));
+        assert!(line.contains("method=POST"));
+        assert!(!line.contains("/api/users"));
+
+        let revealing = PolicyConfig { reveal_match: true, ..Default::default() };
+        assert!(revealing.block_log_line(&sample_block(), &request).contains("path=/api/users"));
+    }
+
+    #[test]
+    fn json_envelope_block_body() {
+        let block = sample_block().with_rule("user.password");
+        let config = PolicyConfig { block_body_json: true, ..Default::default() };
+        let response = config.render_block(&block, &RequestInfo::default(), false);
+        assert!(response.headers.contains(&("content-type".into(), "application/json".into())));
+        let body: serde_json::Value = serde_json::from_slice(&response.body.unwrap()).unwrap();
+        assert_eq!(body, json(r#"{"error":"access_denied","defense":"methylated"}"#));
+
+        let revealing = PolicyConfig { block_body_json: true, reveal_match: true, ..Default::default() };
+        let body: serde_json::Value = serde_json::from_slice(&revealing.render_block(&block, &RequestInfo::default(), false).body.unwrap()).unwrap();
+        assert_eq!(body["rule"], "user.password");
+    }
+
+    #[test]
+    fn cors_headers_on_cross_origin_block() {
+        let config: PolicyConfig = serde_json::from_str(
+            r#"{"block_cors":{"allow_origin":"*","allow_methods":"GET, POST"}}"#,
+        ).unwrap();
+        let request = RequestInfo { origin: Some("https://app.example.com".into()), ..Default::default() };
+        let response = config.render_block(&sample_block(), &request, false);
+        assert_eq!(response.status, 403);
+        assert!(response.headers.contains(&("access-control-allow-origin".into(), "https://app.example.com".into())));
+        assert!(response.headers.contains(&("access-control-allow-methods".into(), "GET, POST".into())));
+
+        let fixed = PolicyConfig {
+            block_cors: Some(BlockCors { allow_origin: "https://a.example".into(), allow_methods: None, allow_headers: None }),
+            ..Default::default()
+        };
+        let response = fixed.render_block(&sample_block(), &request, false);
+        assert!(response.headers.contains(&("access-control-allow-origin".into(), "https://a.example".into())));
+    }
+
+    #[test]
+    fn incident_record_is_one_json_message() {
+        let request = RequestInfo {
+            source: Some("10.0.0.7:4000".into()),
+            method: Some("POST".into()),
+            route: "/api".into(),
+            ..Default::default()
+        };
+        let block = sample_block().with_rule("user.password");
+        let record: serde_json::Value = serde_json::from_slice(
+            &PolicyConfig::default().incident_record(&block, &request, 1_700_000_000)).unwrap();
+        assert_eq!(record, json(r#"{"timestamp":1700000000,"defense":"methylated","source":"10.0.0.7:4000","method":"POST"}"#));
+
+        let revealing = PolicyConfig { reveal_match: true, ..Default::default() };
+        let record: serde_json::Value = serde_json::from_slice(
+            &revealing.incident_record(&block, &request, 0)).unwrap();
+        assert_eq!((record["path"].as_str(), record["rule"].as_str()), (Some("/api"), Some("user.password")));
+    }
+
+    fn test_root(config: PolicyConfig) -> LeukocyteRoot {
+        LeukocyteRoot {
+            config: Rc::new(config),
+            vm: VmConfig::default(),
+            metrics: None,
+            incident_queue: None,
+            config_queue: None,
+        }
+    }
+
+    #[test]
+    fn each_block_enqueues_exactly_one_incident() {
+        let root = LeukocyteRoot { incident_queue: Some(7), ..test_root(PolicyConfig::default()) };
+        let filter = root.filter_for(1);
+        let mut sent = Vec::new();
+        filter.report_incident(&sample_block(), 1_700_000_000, |queue, record| {
+            sent.push((queue, record.to_vec()));
+            Ok(())
+        });
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, 7);
+        let record: serde_json::Value = serde_json::from_slice(&sent[0].1).unwrap();
+        assert_eq!(record["defense"], "methylated");
+
+        // A host error is swallowed rather than trapping the request
+        filter.report_incident(&sample_block(), 0, |_, _| Err(Status::InternalFailure));
+
+        let unqueued = test_root(PolicyConfig::default()).filter_for(1);
+        unqueued.report_incident(&sample_block(), 0, |_, _| panic!("no queue registered"));
+    }
+
+    #[test]
+    fn grpc_block_yields_grpc_status() {
+        assert!(expects_grpc_reply("application/grpc+proto"));
+        assert!(!expects_grpc_reply("application/json"));
+
+        let response = PolicyConfig::default().render_block(&sample_block(), &RequestInfo::default(), true);
+        assert_eq!(response.status, 200);
+        assert!(response.headers.contains(&("grpc-status".into(), "7".into())));
+        assert!(response.headers.contains(&("grpc-message".into(), sample_block().reason.into())));
+
+        let unauthenticated = PolicyConfig { grpc_block_status: 16, ..Default::default() };
+        let response = unauthenticated.render_block(&sample_block(), &RequestInfo::default(), true);
+        assert!(response.headers.contains(&("grpc-status".into(), "16".into())));
+    }
+
+    fn csv_config() -> PolicyConfig {
+        let mut config = PolicyConfig {
+            suppression_paths: ["ssn".to_string()].into(),
+            value_deny_regexes: vec!["(?i)<script".into()],
+            ..Default::default()
+        };
+        assert!(config.compile().is_empty());
+        config
+    }
+
+    #[test]
+    fn csv_with_suppressed_column_is_blocked() {
+        let config = csv_config();
+        assert_eq!(config.body_kind(Some("text/csv; charset=utf-8")), Some(BodyKind::Csv));
+
+        let body = b"name,\"Address, Line 1\",SSN\r\n\"Doe, Jane\",\"1 \"\"Main\"\" St\",123-45-6789\r\n";
+        let block = config.inspect_csv(body).unwrap_err();
+        assert_eq!((block.defense, block.rule.as_deref()), ("methylated", Some("ssn")));
+        assert_eq!(parse_csv(std::str::from_utf8(body).unwrap())[1][1], "1 \"Main\" St");
+    }
+
+    #[test]
+    fn clean_csv_passes_and_values_are_opt_in() {
+        let mut config = csv_config();
+        let body = b"name,comment\nJane,\"<script>alert(1)</script>\"\n";
+        assert!(config.inspect_csv(body).is_ok());
+
+        config.csv_scan_values = true;
+        assert_eq!(config.inspect_csv(body).unwrap_err().defense, "denied-value");
+        assert!(config.inspect_json(&json(r#"{"c":["<SCRIPT>"]}"#)).is_err());
+    }
+
+    #[test]
+    fn composite_rule_needs_full_combination() {
+        let config: PolicyConfig = serde_json::from_str(r#"{"composite_rules":[{
+            "all_of":[{"path":"user.role","value":"admin"},{"path":"action","value":"delete"}],
+            "action":"deny"
+        }]}"#).unwrap();
+
+        let block = config.inspect_json(&json(r#"{"user":{"role":"admin"},"action":"delete"}"#)).unwrap_err();
+        assert_eq!(block.defense, "composite-rule");
+        assert!(config.inspect_json(&json(r#"{"user":{"role":"admin"},"action":"read"}"#)).is_ok());
+        assert!(config.inspect_json(&json(r#"{"user":{"role":"viewer"},"action":"delete"}"#)).is_ok());
+        assert!(config.inspect_json(&json(r#"{"action":"delete"}"#)).is_ok());
+    }
+
+    #[test]
+    fn nested_or_of_ands_decides() {
+        let config: PolicyConfig = serde_json::from_str(r#"{"rule_groups":[
+            {"name":"trusted-health","action":"allow",
+             "when":{"attribute":"path","equals":"/healthz"}},
+            {"name":"destructive-admin","severity":"critical","action":"deny",
+             "when":{"any_of":[
+                {"all_of":[{"attribute":"method","equals":"DELETE"},{"path":"user.role","value":"admin"}]},
+                {"all_of":[{"path":"action","value":"drop"},{"path":"confirm"}]}
+             ]}}
+        ]}"#).unwrap();
+        let delete = RequestInfo { method: Some("DELETE".into()), route: "/users".into(), ..Default::default() };
+        let post = RequestInfo { method: Some("POST".into()), route: "/users".into(), ..Default::default() };
+
+        let block = config.evaluate_groups(&delete, Some(&json(r#"{"user":{"role":"admin"}}"#))).unwrap_err();
+        assert_eq!((block.rule.as_deref(), block.severity), (Some("destructive-admin"), Some(Severity::Critical)));
+        assert!(config.evaluate_groups(&post, Some(&json(r#"{"user":{"role":"admin"}}"#))).is_ok());
+        assert!(config.evaluate_groups(&post, Some(&json(r#"{"action":"drop","confirm":true}"#))).is_err());
+        assert!(config.evaluate_groups(&post, Some(&json(r#"{"action":"drop"}"#))).is_ok());
+        assert!(config.evaluate_groups(&delete, None).is_ok());
+
+        let health = RequestInfo { route: "/healthz".into(), ..delete };
+        assert!(config.evaluate_groups(&health, Some(&json(r#"{"user":{"role":"admin"}}"#))).is_ok());
+    }
+
+    #[test]
+    fn prefix_window_limits_what_is_inspected() {
+        let config = PolicyConfig {
+            suppression_paths: ["token".to_string()].into(),
+            inspect_prefix_bytes: Some(32),
+            ..Default::default()
+        };
+        let early = br#"{"token":"abc","padding":"xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx"}"#;
+        let late = br#"{"padding":"xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx","token":"abc"}"#;
+        assert_eq!(config.inspect_prefix_window(early).unwrap_err().defense, "methylated");
+        assert!(config.inspect_prefix_window(late).is_ok());
+    }
+
+    #[test]
+    fn invalid_utf8_body_blocks_in_strict_mode() {
+        let strict = PolicyConfig { allow_paths: ["id".to_string()].into(), ..Default::default() };
+        let body = b"{\"id\":\"\xff\xfe\"}";
+        assert_eq!(strict.parse_json_body(body).unwrap_err().defense, "unparseable-body");
+        assert_eq!(strict.parse_json_body(b"").unwrap(), None);
+        assert!(strict.parse_json_body(br#"{"id":1}"#).unwrap().is_some());
+    }
+
+    #[test]
+    fn unparseable_action_is_configurable() {
+        let lenient = PolicyConfig {
+            allow_paths: ["id".to_string()].into(),
+            on_unparseable: Some(UnparseableAction::Allow),
+            ..Default::default()
+        };
+        assert_eq!(lenient.parse_json_body(b"\xff").unwrap(), None);
+
+        let suppress_only = PolicyConfig { suppression_paths: ["x".to_string()].into(), ..Default::default() };
+        assert_eq!(suppress_only.parse_json_body(b"\xff").unwrap(), None);
+        let blocking = PolicyConfig { on_unparseable: Some(UnparseableAction::Block), ..suppress_only };
+        assert!(blocking.parse_json_body(b"not json").is_err());
+    }
+
+    #[test]
+    fn denied_array_element_blocks() {
+        let mut config = PolicyConfig::default();
+        config.array_value_deny.insert("user.roles".into(), ["root".to_string(), "0".to_string()].into());
+        config.array_value_deny.insert("ids".into(), ["0".to_string()].into());
+
+        let block = config.inspect_json(&json(r#"{"user":{"roles":["user","root"]}}"#)).unwrap_err();
+        assert_eq!(block.defense, "denied-array-value");
+        assert!(config.inspect_json(&json(r#"{"ids":[3,0]}"#)).is_err());
+    }
+
+    #[test]
+    fn value_snippet_is_truncated_and_masked() {
+        assert_eq!(mask_snippet("administrator", 8), "ad****st");
+        assert_eq!(mask_snippet("root", 16), "r**t");
+        assert_eq!(mask_snippet("ab", 16), "**");
+
+        let mut config = PolicyConfig::default();
+        config.array_value_deny.insert("roles".into(), ["superadministrator".to_string()].into());
+        let body = json(r#"{"roles":["superadministrator"]}"#);
+
+        let quiet = config.inspect_json(&body).unwrap_err();
+        let line = config.block_log_line(&quiet, &RequestInfo::default());
+        assert!(line.contains("value=<redacted>") && !line.contains("super"));
+
+        config.log_value_snippet = ValueSnippet { enabled: true, max_len: 12 };
+        let snippet = config.inspect_json(&body).unwrap_err();
+        assert!(config.block_log_line(&snippet, &RequestInfo::default()).contains("value=sup******nis"));
+    }
+
+    #[test]
+    fn allowed_array_elements_pass() {
+        let mut config = PolicyConfig::default();
+        config.array_value_deny.insert("user.roles".into(), ["root".to_string()].into());
+
+        assert!(config.inspect_json(&json(r#"{"user":{"roles":["user","editor"]}}"#)).is_ok());
+        assert!(config.inspect_json(&json(r#"{"user":{"roles":"root"}}"#)).is_ok());
+    }
+
+    #[test]
+    fn pushed_config_replaces_active_policy() {
+        let mut root = test_root(PolicyConfig::default());
+        let in_flight = Rc::clone(&root.config);
+
+        assert!(root.apply_pushed_config(br#"{"allow_paths":["User.Name"]}"#));
+        assert!(root.config.allow_paths.contains("user.name"));
+        assert!(in_flight.allow_paths.is_empty());
+
+        assert!(!root.apply_pushed_config(b"{not json"));
+        assert!(root.config.allow_paths.contains("user.name"));
+    }
+
+    #[test]
+    fn non_ascii_header_names_fold_when_normalization_enabled() {
+        let fullwidth = headers(&[("ｘ－ａｐｉ－ｋｅｙ", "secret")]);
+        let mut config = PolicyConfig {
+            suppression_paths: ["x-api-key".to_string()].into(),
+            ..Default::default()
+        };
+        assert!(config.inspect_headers(&fullwidth).is_ok());
+
+        config.normalize_header_names = true;
+        assert!(config.inspect_headers(&fullwidth).is_err());
+        assert!(config.inspect_headers(&headers(&[("x-request-id", "1")])).is_ok());
+    }
+
+    #[test]
+    fn each_bypass_point_records_its_own_reason() {
+        let config = PolicyConfig::load(br#"{
+            "bypass_paths": ["/healthz"],
+            "passthrough_options": true,
+            "body_inspection_sample": 0.5
+        }"#).unwrap();
+        let post = |route: &str| RequestInfo { route: route.into(), method: Some("POST".into()), ..Default::default() };
+        let json = Some(BodyKind::Json);
+        let reason = |request: &RequestInfo, headers: &[(String, String)], kind, end_of_stream, roll: f64| {
+            config.passthrough_for(request, headers, kind, end_of_stream, || roll)
+        };
+        assert_eq!(reason(&post("/healthz"), &[], json, false, 0.0), Some(Passthrough::HealthCheck));
+        let upgrade = headers(&[("connection", "Upgrade"), ("upgrade", "websocket")]);
+        assert_eq!(reason(&post("/ws"), &upgrade, None, false, 0.0), Some(Passthrough::WebSocket));
+        assert_eq!(reason(&post("/upload"), &[], None, false, 0.0), Some(Passthrough::ContentType));
+        let options = RequestInfo { method: Some("OPTIONS".into()), ..post("/api") };
+        assert_eq!(reason(&options, &[], json, false, 0.0), Some(Passthrough::Preflight));
+        assert_eq!(reason(&post("/api"), &[], json, false, 0.9), Some(Passthrough::SampledOut));
+
+        // Inspected, or nothing to inspect: no passthrough, so `inspected` counts it
+        assert_eq!(reason(&post("/api"), &[], json, false, 0.1), None);
+        assert_eq!(reason(&post("/api"), &[], None, true, 0.0), None);
+    }
+
+    #[test]
+    fn each_passthrough_reason_has_its_own_counter() {
+        let metrics = Metrics {
+            inspected: Some(100),
+            passthrough: [Some(1), Some(2), Some(3), Some(4), Some(5)],
+            severity_blocks: [None; 4],
+            inspection_ms: None,
+            policy: [None; 5],
+        };
+        let reasons = [
+            Passthrough::HealthCheck,
+            Passthrough::Preflight,
+            Passthrough::SampledOut,
+            Passthrough::ContentType,
+            Passthrough::WebSocket,
+        ];
+        let ids: HashSet<Option<u32>> = reasons.iter().map(|r| metrics.outcome(Some(*r))).collect();
+        assert_eq!(ids.len(), reasons.len());
+        assert!(!ids.contains(&metrics.inspected));
+        assert_eq!(metrics.outcome(None), Some(100));
+    }
+
+    #[test]
+    fn inspection_time_is_sampled_into_the_histogram() {
+        let started = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        let finished = started + std::time::Duration::from_millis(12);
+        let metrics = Metrics { inspection_ms: Some(7), ..Default::default() };
+        assert_eq!(metrics.inspection_sample(started, finished), Some((7, 12)));
+        // A clock that steps backwards records zero rather than failing
+        assert_eq!(metrics.inspection_sample(finished, started), Some((7, 0)));
+        assert_eq!(Metrics::default().inspection_sample(started, finished), None);
+    }
+
+    #[test]
+    fn websocket_upgrades_are_detected() {
+        assert!(is_websocket_upgrade(&headers(&[("upgrade", "WebSocket")])));
+        assert!(!is_websocket_upgrade(&headers(&[("upgrade", "h2c")])));
+        assert!(!is_websocket_upgrade(&headers(&[("connection", "keep-alive")])));
+    }
+
+    #[test]
+    fn parent_allow_covers_children_up_to_depth() {
+        let shallow = PolicyConfig {
+            allow_paths: ["profile".to_string()].into(),
+            allow_prefix_depth: Some(1),
+            ..Default::default()
+        };
+        assert!(shallow.inspect_json(&json(r#"{"profile":{"name":"a"}}"#)).is_ok());
+        let block = shallow.inspect_json(&json(r#"{"profile":{"address":{"city":"x"}}}"#)).unwrap_err();
+        assert_eq!(block.rule.as_deref(), Some("profile.address.city"));
+
+        let unlimited = PolicyConfig::load(br#"{"allow_paths": ["profile"], "allow_prefix_depth": null}"#).unwrap();
+        assert_eq!(unlimited.allow_prefix_depth, None);
+        assert!(unlimited.inspect_json(&json(r#"{"profile":{"address":{"city":"x"}}}"#)).is_ok());
+        assert!(unlimited.inspect_json(&json(r#"{"other":1}"#)).is_err());
+
+        // Unset, a parent covers nothing below itself
+        assert_eq!(PolicyConfig::default().allow_prefix_depth, Some(0));
+    }
+
+    #[test]
+    fn declared_json_with_non_json_body_is_a_mismatch() {
+        let config = PolicyConfig { enforce_content_type: true, ..Default::default() };
+        let block = config.check_content_type(Some("application/json"), b"name=a&role=admin").unwrap_err();
+        assert_eq!(block.defense, "content-type-mismatch");
+
+        assert!(config.check_content_type(Some("application/json"), b"").is_ok());
+        assert!(config.check_content_type(Some("application/json"), b" \r\n").is_ok());
+        assert!(config.check_content_type(Some("application/json"), br#"{"a":1}"#).is_ok());
+        assert!(config.check_content_type(None, b"not json").is_ok());
+
+        assert!(config.check_content_type(Some("text/plain"), br#"{"role":"admin"}"#).is_err());
+        assert!(config.check_content_type(Some("text/plain"), b"42").is_ok());
+
+        let lenient = PolicyConfig::default();
+        assert!(lenient.check_content_type(Some("application/json"), b"name=a").is_ok());
+        assert_eq!(lenient.body_kind(Some("text/plain")), None);
+        assert_eq!(config.body_kind(Some("text/plain")), Some(BodyKind::Opaque));
+    }
+
+    #[test]
+    fn constrained_allow_entries_check_values() {
+        let config = PolicyConfig::load(br#"{"allow_paths":
+            ["id", {"path":"Status","values":["active","inactive"]}]}"#).unwrap();
+        assert!(config.allow_paths.contains("status"));
+
+        assert!(config.inspect_json(&json(r#"{"id":1,"status":"active"}"#)).is_ok());
+        assert!(config.inspect_json(&json(r#"{"id":1}"#)).is_ok());
+
+        let block = config.inspect_json(&json(r#"{"id":1,"status":"deleted"}"#)).unwrap_err();
+        assert_eq!(block.defense, "value-not-allowed");
+        assert_eq!(block.rule.as_deref(), Some("status"));
+        assert!(config.inspect_json(&json(r#"{"status":{"nested":true}}"#)).is_err());
+    }
+
+    #[test]
+    fn path_count_is_observed_regardless_of_decision() {
+        let body = json(r#"{"id":1,"profile":{"name":"a","tags":[{"k":1}]}}"#);
+
+        let mut observed = None;
+        assert!(PolicyConfig::default().inspect_json_observed(&body, |n| observed = Some(n)).is_ok());
+        assert_eq!(observed, Some(5));
+
+        let strict = PolicyConfig { allow_paths: ["id".to_string()].into(), ..Default::default() };
+        let mut observed = None;
+        assert!(strict.inspect_json_observed(&body, |n| observed = Some(n)).is_err());
+        assert_eq!(observed, Some(5));
+        assert_eq!(PATH_COUNT_PROPERTY.join("."), "leukocyte.path_count");
+    }
+
+    #[test]
+    fn plain_logs_are_ascii_only() {
+        let config = PolicyConfig {
+            plain_logs: true,
+            suppression_paths: ["password".to_string()].into(),
+            ..Default::default()
+        };
+        let block = config.inspect_json(&json(r#"{"password":"x"}"#)).unwrap_err();
+        let request = RequestInfo { method: Some("POST".into()), ..Default::default() };
+
+        let line = config.block_log_line(&block, &request);
+        assert!(line.is_ascii(), "{}", line);
+        assert!(line.starts_with("[Methylation]"));
+        assert_eq!(config.log_text("⚠️ [Leukocyte] Skipping invalid rule: x"), "[Leukocyte] Skipping invalid rule: x");
+
+        assert!(!PolicyConfig::default().block_log_line(&block, &request).is_ascii());
+    }
+
+    const MULTIPART_TYPE: &str = "multipart/form-data; boundary=\"XyZ\"";
+
+    fn multipart_body(parts: &[(&str, Option<&str>, &str)]) -> Vec<u8> {
+        let mut body = String::from("preamble\r\n");
+        for (name, filename, data) in parts {
+            body.push_str("--XyZ\r\nContent-Disposition: form-data; name=\"");
+            body.push_str(name);
+            body.push('"');
+            if let Some(filename) = filename {
+                body.push_str(&format!("; filename=\"{}\"\r\nContent-Type: image/png", filename));
+            }
+            body.push_str("\r\n\r\n");
+            body.push_str(data);
+            body.push_str("\r\n");
+        }
+        body.push_str("--XyZ--\r\n");
+        body.into_bytes()
+    }
+
+    #[test]
+    fn multipart_parts_are_split_by_boundary() {
+        let body = multipart_body(&[("title", None, "hello\r\nworld"), ("avatar", Some("a.png"), "PNG")]);
+        let parts = parse_multipart(&body, &multipart_boundary(MULTIPART_TYPE).unwrap());
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name.as_deref(), Some("title"));
+        assert_eq!(parts[0].data, b"hello\r\nworld");
+        assert!(!parts[0].is_file());
+        assert_eq!(parts[1].filename.as_deref(), Some("a.png"));
+        assert!(parts[1].is_file());
+    }
+
+    #[test]
+    fn suppressed_multipart_field_is_blocked() {
+        let config = PolicyConfig {
+            suppression_paths: ["is_admin".to_string()].into(),
+            ..Default::default()
+        };
+        assert_eq!(config.body_kind(Some(MULTIPART_TYPE)), Some(BodyKind::Multipart));
+
+        let clean = multipart_body(&[("title", None, "hi")]);
+        assert!(config.inspect_multipart(MULTIPART_TYPE, &clean).is_ok());
+
+        let body = multipart_body(&[("title", None, "hi"), ("IS_ADMIN", None, "true")]);
+        let block = config.inspect_multipart(MULTIPART_TYPE, &body).unwrap_err();
+        assert_eq!(block.defense, "methylated");
+        assert_eq!(block.rule.as_deref(), Some("is_admin"));
+    }
+
+    #[test]
+    fn multipart_files_are_size_limited_and_text_values_scanned() {
+        let mut config = PolicyConfig {
+            max_multipart_file_bytes: Some(4),
+            value_deny_regexes: vec!["(?i)<script".to_string()],
+            ..Default::default()
+        };
+        assert!(config.compile().is_empty());
+
+        let upload = multipart_body(&[("avatar", Some("a.png"), "0123456789")]);
+        assert_eq!(config.inspect_multipart(MULTIPART_TYPE, &upload).unwrap_err().defense, "multipart-file-too-large");
+
+        let script = multipart_body(&[("bio", None, "<SCRIPT>alert(1)")]);
+        assert!(config.inspect_multipart(MULTIPART_TYPE, &script).is_ok());
+        config.multipart_scan_values = true;
+        assert_eq!(config.inspect_multipart(MULTIPART_TYPE, &script).unwrap_err().defense, "denied-value");
+    }
+
+    #[test]
+    fn arrays_nested_past_limit_are_blocked() {
+        assert_eq!(array_depth(&json(r#"{"a":{"b":{"c":1}}}"#)), 0);
+        assert_eq!(array_depth(&json(r#"{"a":[{"b":[[1]]}],"c":[2]}"#)), 3);
+
+        let config = PolicyConfig { max_array_depth: Some(2), ..Default::default() };
+        assert!(config.inspect_json(&json(r#"{"m":[[1,2],[3]]}"#)).is_ok());
+        assert!(config.inspect_json(&json(r#"{"a":{"b":{"c":{"d":{"e":1}}}}}"#)).is_ok());
+
+        let block = config.inspect_json(&json(r#"{"m":[[[1]]]}"#)).unwrap_err();
+        assert_eq!(block.defense, "array-depth-exceeded");
+    }
+
+    #[test]
+    fn both_buffering_modes_catch_suppressed_fields() {
+        for (mode, held) in [("pause", Action::Pause), ("stream", Action::Continue)] {
+            let config = PolicyConfig::load(format!(
+                r#"{{"buffering_mode":"{}","suppression_paths":["is_admin"]}}"#, mode).as_bytes()).unwrap();
+            assert_eq!(config.headers_action(true, false), held, "{}", mode);
+            assert_eq!(config.headers_action(false, false), Action::Continue, "{}", mode);
+
+            let body = r#"{"name":"a","is_admin":true}"#;
+            assert!(config.inspect_partial(&body.as_bytes()[..24]).is_err(), "{}", mode);
+            assert_eq!(config.inspect_json(&json(body)).unwrap_err().defense, "methylated", "{}", mode);
+        }
+    }
+
+    #[test]
+    fn suppressed_paths_block_only_above_violation_threshold() {
+        let body = json(r#"{"id":1,"debug":true,"trace":{"on":1},"items":[{"debug":1}]}"#);
+        let config = |threshold| PolicyConfig {
+            suppression_paths: ["debug".to_string(), "trace".to_string(), "items.debug".to_string()].into(),
+            violation_threshold: threshold,
+            ..Default::default()
+        };
+
+        // Default threshold keeps the historical block-on-first-match behavior
+        assert_eq!(config(0).inspect_json_observed(&body, |_| {}).unwrap_err().rule.as_deref(), Some("debug"));
+        assert!(config(2).inspect_json_observed(&body, |_| {}).is_err());
+
+        let tolerated = config(3).inspect_json_observed(&body, |_| {}).unwrap().tolerated;
+        assert_eq!(tolerated.len(), 3);
+        let mut stripped = body.clone();
+        strip_paths(&mut stripped, "", &tolerated);
+        assert_eq!(stripped, json(r#"{"id":1,"items":[{}]}"#));
+
+        assert_eq!(config(4).inspect_json_observed(&body, |_| {}).unwrap().tolerated.len(), 3);
+    }
+
+    #[test]
+    fn tolerated_paths_are_exempt_from_strict_allow() {
+        let config = PolicyConfig {
+            suppression_paths: ["debug".to_string()].into(),
+            allow_paths: ["id".to_string()].into(),
+            violation_threshold: 1,
+            ..Default::default()
+        };
+        assert!(config.inspect_json(&json(r#"{"id":1,"debug":{"level":2}}"#)).is_ok());
+        assert!(config.inspect_json(&json(r#"{"id":1,"other":2}"#)).is_err());
+    }
+
+    #[test]
+    fn grpc_web_bypasses_json_parsing() {
+        let config = PolicyConfig {
+            enforce_content_type: true,
+            suppression_paths: ["x-debug".to_string()].into(),
+            ..Default::default()
+        };
+        assert_eq!(config.body_kind(Some("application/grpc-web+proto")), None);
+        assert_eq!(config.body_kind(Some("application/grpc-web-text")), None);
+
+        let scanning = PolicyConfig { grpc_web_text_scan: true, ..config };
+        assert_eq!(scanning.body_kind(Some("application/grpc-web+proto")), None);
+        assert_eq!(scanning.body_kind(Some("application/grpc-web-text+proto")), Some(BodyKind::GrpcWebText));
+    }
+
+    #[test]
+    fn grpc_web_text_trailer_metadata_is_checked() {
+        use base64::Engine;
+        let frame = |flags: u8, payload: &[u8]| {
+            let mut f = vec![flags];
+            f.extend((payload.len() as u32).to_be_bytes());
+            f.extend(payload);
+            base64::engine::general_purpose::STANDARD.encode(f)
+        };
+        let config = PolicyConfig {
+            grpc_web_text_scan: true,
+            suppression_paths: ["x-debug".to_string()].into(),
+            ..Default::default()
+        };
+
+        let clean = frame(0, b"\x0a\x03abc") + &frame(0x80, b"grpc-status: 0\r\n");
+        assert!(config.inspect_grpc_web_text(clean.as_bytes()).is_ok());
+
+        let tainted = frame(0, b"x-debug") + &frame(0x80, b"X-Debug: 1\r\n");
+        assert_eq!(config.inspect_grpc_web_text(tainted.as_bytes()).unwrap_err().defense, "methylated-header");
+        assert!(config.inspect_grpc_web_text(b"%%%not base64").is_ok());
+    }
+
+    #[test]
+    fn config_status_reflects_load_outcome() {
+        assert_eq!(config_status(&PolicyConfig::load(br#"{"suppression_paths":["a"]}"#)), "ok");
+
+        let bad_rule = PolicyConfig::load(br#"{"value_deny_regexes":["(unclosed", "ok"]}"#);
+        let status = config_status(&bad_rule);
+        assert!(status.starts_with("error: 1 invalid rule(s): (unclosed"), "{}", status);
+
+        let status = config_status(&PolicyConfig::load(b"{\"allow_paths\": 5}"));
+        assert!(status.starts_with("error: invalid type"), "{}", status);
+    }
+
+    #[test]
+    fn failed_load_still_publishes_its_status() {
+        let root = test_root(PolicyConfig::default());
+        let mut published = None;
+        root.publish_config_status(&PolicyConfig::load(b"{not json"), |key, status| {
+            published = Some((key.to_string(), String::from_utf8(status.to_vec()).unwrap()));
+            Ok(())
+        });
+        let (key, status) = published.unwrap();
+        assert_eq!(key, "leukocyte.config_status");
+        assert!(status.starts_with("error: "), "{}", status);
+
+        // A host that refuses the write does not fail the load
+        root.publish_config_status(&PolicyConfig::load(b"{}"), |_, _| Err(Status::InternalFailure));
+    }
+
+    #[test]
+    fn missing_required_header_is_blocked() {
+        let config = PolicyConfig::load(br#"{"required_headers":["X-Request-Id","traceparent"]}"#).unwrap();
+        let present = headers(&[("x-request-id", "1"), ("traceparent", "00-ab-cd-01")]);
+        assert!(config.check_required_headers(&present).is_ok());
+
+        let block = config.check_required_headers(&headers(&[("x-request-id", "1")])).unwrap_err();
+        assert_eq!(block.defense, "missing-header");
+        assert_eq!(block.rule.as_deref(), Some("traceparent"));
+
+        assert!(PolicyConfig::default().check_required_headers(&[]).is_ok());
+    }
+
+    #[test]
+    fn stripped_headers_are_removed_without_blocking() {
+        let config = PolicyConfig::load(br#"{"strip_headers":["X-Internal-User"],"suppression_paths":["x-evil"]}"#).unwrap();
+        let request = headers(&[("host", "a"), ("X-Internal-User", "42"), ("x-request-id", "1")]);
+
+        assert!(config.inspect_headers(&request).is_ok());
+        assert!(config.check_required_headers(&request).is_ok());
+        assert_eq!(config.headers_to_strip(&request, true), vec!["X-Internal-User"]);
+        assert!(PolicyConfig::default().headers_to_strip(&request, true).is_empty());
+    }
+
+    #[test]
+    fn content_length_is_dropped_when_the_body_may_be_rewritten() {
+        let request = headers(&[("host", "a"), ("Content-Length", "42")]);
+        let tolerant = PolicyConfig::load(br#"{"violation_threshold": 1}"#).unwrap();
+
+        assert_eq!(tolerant.headers_to_strip(&request, true), vec!["Content-Length"]);
+        assert!(tolerant.headers_to_strip(&request, false).is_empty());
+        assert!(PolicyConfig::default().headers_to_strip(&request, true).is_empty());
+
+        let scrubbing = PolicyConfig::load(br#"{"allow_paths": ["id"], "scrub_unallowed": true}"#).unwrap();
+        assert_eq!(scrubbing.headers_to_strip(&request, true), vec!["Content-Length"]);
+    }
+
+    #[test]
+    fn failed_recompression_blocks_instead_of_emptying_the_body() {
+        assert_eq!(recompress(b"{}".to_vec(), false, |_| None).unwrap(), b"{}");
+        let gzipped = recompress(b"{}".to_vec(), true, gzip).unwrap();
+        assert_eq!(gunzip(&gzipped, 1024).unwrap(), b"{}");
+        assert_eq!(recompress(b"{}".to_vec(), true, |_| None).unwrap_err().defense, "rewrite-failed");
+    }
+
+    #[test]
+    fn header_suppression_is_case_insensitive_once_normalized() {
+        let config = PolicyConfig::load(br#"{"suppression_paths":["X-Api-Key"]}"#).unwrap();
+        for name in ["x-api-key", "X-API-KEY", "X-Api-Key"] {
+            let block = config.inspect_headers(&headers(&[(name, "k")])).unwrap_err();
+            assert_eq!(block.rule.as_deref(), Some("x-api-key"), "{}", name);
+        }
+        assert!(config.inspect_headers(&headers(&[("x-api-keys", "k")])).is_ok());
+
+        assert!(matches!(config.normalize_header_name("content-type"), Cow::Borrowed("content-type")));
+        assert_eq!(config.normalize_header_name("Content-Type"), "content-type");
+    }
+
+    #[test]
+    fn sub_policies_inherit_defaults_and_override_fields() {
+        let config = PolicyConfig::load(br#"{
+            "suppression_paths": ["root_only"],
+            "defaults": {"block_status": 404, "suppression_paths": ["password"]},
+            "sub_policies": [
+                {"match": {"path_prefix": "/admin", "methods": ["post"]}, "suppression_paths": ["Token"]},
+                {"match": {"host": "tenant.example.com"}}
+            ]
+        }"#).unwrap();
+        let request = |method: &str, route: &str, host: &str| RequestInfo {
+            method: Some(method.into()),
+            route: route.into(),
+            host: Some(host.into()),
+            ..Default::default()
+        };
+
+        let admin = config.sub_policy_for(&request("POST", "/admin/users", "a")).unwrap();
+        assert_eq!(admin.block_status, 404);
+        assert_eq!(admin.suppression_paths, ["token".to_string()].into());
+        let block = admin.inspect_json(&json(r#"{"token":1}"#)).unwrap_err();
+        assert_eq!(admin.render_block(&block, &RequestInfo::default(), false).status, 404);
+
+        let tenant = config.sub_policy_for(&request("GET", "/", "Tenant.Example.com:8443")).unwrap();
+        assert_eq!(tenant.suppression_paths, ["password".to_string()].into());
+
+        assert!(config.sub_policy_for(&request("GET", "/admin/users", "a")).is_none());
+        assert_eq!(config.block_status, 403);
+    }
+
+    #[test]
+    fn non_object_top_level_json_is_blocked_when_required() {
+        let config = PolicyConfig { require_json_object: true, ..Default::default() };
+        assert!(config.inspect_json(&json(r#"{"a":[1]}"#)).is_ok());
+        assert_eq!(config.inspect_json(&json(r#"[{"a":1}]"#)).unwrap_err().defense, "expected-object");
+        assert_eq!(config.inspect_json(&json("42")).unwrap_err().defense, "expected-object");
+        assert!(config.inspect_json(&json(r#""text""#)).is_err());
+
+        assert!(PolicyConfig::default().inspect_json(&json("[1]")).is_ok());
+    }
+
+    #[test]
+    fn cidr_membership() {
+        let net = Cidr::parse("10.0.0.0/8").unwrap();
+        assert!(net.contains(&"10.200.1.1".parse().unwrap()));
+        assert!(!net.contains(&"11.0.0.1".parse().unwrap()));
+        assert!(Cidr::parse("0.0.0.0/0").unwrap().contains(&"8.8.8.8".parse().unwrap()));
+        assert!(Cidr::parse("fd00::/8").unwrap().contains(&"fd12::1".parse().unwrap()));
+        assert!(Cidr::parse("10.0.0.0/33").is_none());
+        assert!(Cidr::parse("not-an-ip").is_none());
+    }
+
+    #[test]
+    fn forwarded_for_is_only_trusted_from_trusted_proxies() {
+        let config = PolicyConfig::load(br#"{"trusted_proxies":["10.0.0.0/8","192.168.1.1"]}"#).unwrap();
+
+        // Trusted load balancer: rightmost untrusted hop is the client
+        assert_eq!(config.client_address("10.1.2.3:5000", Some("203.0.113.9, 192.168.1.1")), "203.0.113.9");
+        // Untrusted peer: its x-forwarded-for is ignored entirely
+        assert_eq!(config.client_address("198.51.100.7:4000", Some("1.2.3.4")), "198.51.100.7");
+        // Spoofed leftmost entries cannot displace the real client
+        assert_eq!(config.client_address("10.1.2.3:5000", Some("6.6.6.6, 203.0.113.9")), "203.0.113.9");
+        // Nothing forwarded: the trusted peer itself
+        assert_eq!(config.client_address("10.1.2.3:5000", None), "10.1.2.3");
+        assert_eq!(config.client_address("10.1.2.3:5000", Some("garbage, 10.9.9.9")), "10.9.9.9");
+
+        let bad = PolicyConfig::load(br#"{"trusted_proxies":["10.0.0.0/99"]}"#).unwrap();
+        assert_eq!(bad.compile_errors.len(), 1);
+    }
+
+    #[test]
+    fn route_gate_admits_only_listed_patterns() {
+        let config = PolicyConfig::load(br#"{"allowed_route_patterns":["/api/v1/users/*","/static/**","/health"]}"#).unwrap();
+        assert!(config.check_route_allowed("/api/v1/users/42").is_ok());
+        assert!(config.check_route_allowed("/static/css/site.css").is_ok());
+        assert!(config.check_route_allowed("/health").is_ok());
+
+        let block = config.check_route_allowed("/api/v1/users/42/delete").unwrap_err();
+        assert_eq!(block.defense, "route-not-allowed");
+        // The route is only logged as `path=` under `reveal_match`
+        assert!(!config.block_log_line(&block, &RequestInfo::default()).contains("/api/v1/users"));
+        assert!(config.check_route_allowed("/static/../admin").is_err());
+        assert!(config.check_route_allowed("/healthz").is_err());
+
+        assert!(PolicyConfig::default().check_route_allowed("/anything").is_ok());
+    }
+
+    #[test]
+    fn disallowed_protocol_is_blocked() {
+        let config = PolicyConfig::load(br#"{"allowed_protocols":["HTTP/2","http/1.1"]}"#).unwrap();
+        assert!(config.inspect_protocol(Some("HTTP/2")).is_ok());
+        assert!(config.inspect_protocol(Some("HTTP/1.1")).is_ok());
+        assert_eq!(config.inspect_protocol(Some("HTTP/1.0")).unwrap_err().defense, "protocol-not-allowed");
+        assert!(config.inspect_protocol(None).is_ok());
+
+        assert!(PolicyConfig::default().inspect_protocol(Some("HTTP/1.0")).is_ok());
+    }
+
+    #[test]
+    fn value_rule_blocks_never_log_raw_values() {
+        let secret = "sk_live_0123456789abcdef";
+        let mut config = PolicyConfig {
+            value_deny_regexes: vec!["^sk_live_".to_string()],
+            reveal_match: true,
+            ..Default::default()
+        };
+        assert!(config.compile().is_empty());
+        let block = config.inspect_json(&json(&format!(r#"{{"token":"{}"}}"#, secret))).unwrap_err();
+        let request = RequestInfo { path: Some("/pay".into()), ..Default::default() };
+
+        for line in [config.block_log_line(&block, &request), block.detail.clone(), format!("{:?}", block)] {
+            assert!(!line.contains(secret), "{}", line);
+        }
+        assert!(config.block_log_line(&block, &request).contains("value=<redacted>"));
+
+        config.log_value_snippet = ValueSnippet { enabled: true, max_len: 8 };
+        let line = config.block_log_line(&block, &request);
+        assert!(!line.contains(secret) && line.contains("value=sk****e_"), "{}", line);
+
+        // Request data other than body values goes through the same helper
+        let config = PolicyConfig {
+            reject_double_encoding: true,
+            require_origin: true,
+            allowed_origins: ["https://app.example".to_string()].into(),
+            ..Default::default()
+        };
+        let double_encoded = config.inspect_uri(&format!("/pay?token={}%252F", secret)).unwrap_err();
+        let origin = config.check_origin(Some("POST"), Some(&format!("https://{}.example", secret)), None).unwrap_err();
+        for block in [double_encoded, origin] {
+            for line in [config.block_log_line(&block, &request), block.detail.clone(), format!("{:?}", block)] {
+                assert!(!line.contains(secret), "{}", line);
+            }
+        }
+    }
+
+    #[test]
+    fn denied_key_names_match_at_any_depth() {
+        let config = PolicyConfig::load(br#"{"deny_key_names":["Password"]}"#).unwrap();
+        for body in [
+            r#"{"password":"x"}"#,
+            r#"{"user":{"password":"x"}}"#,
+            r#"{"a":{"b":[{"PASSWORD":"x"}]}}"#,
+        ] {
+            let block = config.inspect_json(&json(body)).unwrap_err();
+            assert_eq!(block.rule.as_deref(), Some("password"), "{}", body);
+        }
+        assert!(config.inspect_json(&json(r#"{"password_hint":"x","user":{"pass":"y"}}"#)).is_ok());
+    }
+
+    #[test]
+    fn summary_line_is_emitted_once_with_decision_fields() {
+        let config = PolicyConfig { summary_log: true, ..Default::default() };
+        let request = RequestInfo { method: Some("POST".into()), path: Some("/api".into()), ..Default::default() };
+        let block = sample_block().with_rule("user.password");
+
+        let mut summary = RequestSummary { path_count: Some(4), ..Default::default() };
+        let line = summary.finish(&config, &request, None, Some(&block)).unwrap();
+        assert_eq!(line, "🧬 [Leukocyte] Request summary | decision=block defense=methylated rule=user.password paths=4 method=POST");
+        assert_eq!(summary.finish(&config, &request, None, None), None);
+
+        let mut summary = RequestSummary::default();
+        let line = summary.finish(&config, &request, Some(Passthrough::SampledOut), None).unwrap();
+        assert!(line.ends_with("decision=passthrough:sampled_out defense=- rule=- paths=- method=POST"), "{}", line);
+
+        let mut quiet = RequestSummary::default();
+        assert_eq!(quiet.finish(&PolicyConfig::default(), &request, None, None), None);
+    }
+
+    #[test]
+    fn on_log_line_reports_the_stored_decision() {
+        let config = PolicyConfig { decision_log: true, ..Default::default() };
+        let request = RequestInfo { request_id: Some("req-42".into()), ..Default::default() };
+
+        let mut summary = RequestSummary::default();
+        assert_eq!(summary.decision_log_line(&config, &request).unwrap(),
+            "🧬 [Leukocyte] Final decision | request_id=req-42 decision=incomplete defense=- rule=-");
+
+        // Recorded even with summary_log off, and only the first decision counts
+        assert_eq!(summary.finish(&config, &request, None, Some(&sample_block().with_rule("ssn"))), None);
+        summary.finish(&config, &request, None, None);
+        assert_eq!(summary.decision_log_line(&config, &request).unwrap(),
+            "🧬 [Leukocyte] Final decision | request_id=req-42 decision=block defense=methylated rule=ssn");
+
+        assert_eq!(summary.decision_log_line(&PolicyConfig::default(), &request), None);
+    }
+
+    #[test]
+    fn policy_selection_reads_configured_header_names() {
+        let config = PolicyConfig::load(br#"{
+            "header_names": {"method": "x-original-method"},
+            "sub_policies": [{"match": {"methods": ["DELETE"]}, "block_status": 405}]
+        }"#).unwrap();
+        let request_headers: HashMap<&str, &str> =
+            [("x-original-method", "DELETE"), (":method", "POST"), (":path", "/a?b=1"), (":authority", "h")].into();
+        let lookup = |name: &str| request_headers.get(name).map(|v| v.to_string());
+
+        let request = config.request_info(None, lookup);
+        assert_eq!(request.method.as_deref(), Some("DELETE"));
+        assert_eq!((request.route.as_str(), request.host.as_deref()), ("/a", Some("h")));
+        assert_eq!(config.sub_policy_for(&request).unwrap().block_status, 405);
+
+        let standard = PolicyConfig::default().request_info(None, lookup);
+        assert_eq!(standard.method.as_deref(), Some("POST"));
+    }
+
+    #[test]
+    fn raw_body_scan_catches_unparseable_payloads() {
+        let mut config = PolicyConfig {
+            body_raw_deny_regexes: vec!["(?i)<script".to_string()],
+            raw_scan_max_bytes: 64,
+            ..Default::default()
+        };
+        assert!(config.compile().is_empty());
+        let body = br#"{"comment": <SCRIPT>alert(1)</SCRIPT>"#;
+
+        // The JSON stage never sees this body: it does not parse
+        assert_eq!(config.parse_json_body(body).unwrap(), None);
+        assert_eq!(config.inspect_raw_body(body).unwrap_err().defense, "raw-body-match");
+
+        let mut padded = vec![b' '; 64];
+        padded.extend_from_slice(body);
+        assert!(config.inspect_raw_body(&padded).is_ok());
+        assert!(config.inspect_raw_body(br#"{"comment":"fine"}"#).is_ok());
+    }
+
+    #[test]
+    fn empty_body_action_applies_only_under_strict_allowlist() {
+        let allowing = PolicyConfig::load(br#"{"allow_paths": ["user.name"]}"#).unwrap();
+        assert!(allowing.check_empty_body(0).is_ok());
+
+        let denying = PolicyConfig::load(br#"{"allow_paths": ["user.name"], "empty_body_action": "deny"}"#).unwrap();
+        assert_eq!(denying.check_empty_body(0).unwrap_err().defense, "empty-body");
+        assert!(denying.check_empty_body(17).is_ok());
+
+        let suppress_only = PolicyConfig::load(br#"{"suppression_paths": ["password"], "empty_body_action": "deny"}"#).unwrap();
+        assert!(suppress_only.check_empty_body(0).is_ok());
+    }
+
+    #[test]
+    fn bodyless_requests_meet_the_empty_body_action() {
+        let denying = PolicyConfig::load(br#"{"allow_paths": ["user.name"], "empty_body_action": "deny"}"#).unwrap();
+        // Headers ending the stream mean no body callback will follow
+        assert_eq!(denying.check_bodyless(true).unwrap_err().defense, "empty-body");
+        assert!(denying.check_bodyless(false).is_ok());
+
+        let allowing = PolicyConfig::load(br#"{"allow_paths": ["user.name"]}"#).unwrap();
+        assert!(allowing.check_bodyless(true).is_ok());
+    }
+
+    #[test]
+    fn header_names_can_be_suppressed_by_regex() {
+        let config = PolicyConfig::load(br#"{"suppression_header_regexes": ["x-.*-internal", "(unclosed"]}"#).unwrap();
+        assert_eq!(config.compile_errors.len(), 1);
+
+        let block = config.inspect_headers(&headers(&[("X-Billing-Internal", "1")])).unwrap_err();
+        assert_eq!(block.defense, "methylated-header");
+        assert!(config.inspect_headers(&headers(&[("x-billing-internal-id", "1")])).is_ok());
+        assert!(config.inspect_headers(&headers(&[("x-request-id", "1")])).is_ok());
+    }
+
+    #[test]
+    fn non_json_action_decides_unparseable_bodies() {
+        let base = br#"{"suppression_paths": ["password"], "body_raw_deny_regexes": ["DROP TABLE"], "raw_scan_max_bytes": 8"#;
+        let load = |action: &str| {
+            let mut config = base.to_vec();
+            config.extend_from_slice(format!(r#", "non_json_action": "{}", "on_unparseable": "block"}}"#, action).as_bytes());
+            PolicyConfig::load(&config).unwrap()
+        };
+        let body = b"name=x; DROP TABLE users";
+        let benign = b"name=x";
+
+        let allowing = load("allow");
+        assert_eq!(allowing.parse_json_body(body).unwrap(), None);
+
+        let blocking = load("block");
+        assert_eq!(blocking.parse_json_body(benign).unwrap_err().defense, "non-json-body");
+
+        // The pre-parse window is too short to reach the match; inspect_raw scans it all
+        let raw = load("inspect_raw");
+        assert!(raw.inspect_raw_body(body).is_ok());
+        assert_eq!(raw.parse_json_body(body).unwrap_err().defense, "raw-body-match");
+        assert_eq!(raw.parse_json_body(benign).unwrap(), None);
+    }
+
+    #[test]
+    fn suppression_entries_can_require_an_exact_value() {
+        let config = PolicyConfig::load(br#"{"suppression_paths": [
+            "debug",
+            {"path": "user.role", "value": "admin"},
+            {"path": "user.Flags.beta", "value": true}
+        ]}"#).unwrap();
+        assert_eq!(config.suppression_paths, ["debug".to_string()].into());
+
+        let block = config.inspect_json(&json(r#"{"user": {"role": "admin"}}"#)).unwrap_err();
+        assert_eq!(block.rule.as_deref(), Some("user.role"));
+        assert!(config.inspect_json(&json(r#"{"user": {"flags": {"beta": true}}}"#)).is_err());
+        assert!(config.inspect_json(&json(r#"{"debug": 0}"#)).is_err());
+
+        assert!(config.inspect_json(&json(r#"{"user": {"role": "viewer"}}"#)).is_ok());
+        assert!(config.inspect_json(&json(r#"{"user": {"role": "Admin", "flags": {"beta": "true"}}}"#)).is_ok());
+    }
+
+    #[test]
+    fn recent_blocks_keep_the_latest_entries_up_to_capacity() {
+        let recent = RecentBlocks { capacity: 3, route: "/_leukocyte/blocks".into() };
+        let mut stored: Option<Vec<u8>> = None;
+        for i in 0..5 {
+            stored = Some(recent.record(stored.as_deref(), serde_json::json!(i)));
+        }
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&stored.unwrap()).unwrap(), json("[2, 3, 4]"));
+        assert_eq!(recent.record(Some(b"garbage"), serde_json::json!(9)), b"[9]");
+
+        let request = RequestInfo { route: "/api/users".into(), ..Default::default() };
+        let entry = PolicyConfig::default().recent_block_entry(&sample_block(), &request, 1_700_000_000);
+        assert_eq!(entry, json(r#"{"timestamp":1700000000,"defense":"methylated","path":"<redacted>"}"#));
+        let revealing = PolicyConfig { reveal_match: true, ..Default::default() };
+        assert_eq!(revealing.recent_block_entry(&sample_block(), &request, 0)["path"], "/api/users");
+    }
+
+    #[test]
+    fn paranoid_turns_on_every_ambiguity_check() {
+        let relaxed = PolicyConfig::load(br#"{"non_json_action": "allow"}"#).unwrap();
+        let paranoid = PolicyConfig::load(br#"{"paranoid": true, "non_json_action": "allow"}"#).unwrap();
+        let parse_block = |config: &PolicyConfig, body: &[u8]| {
+            config.parse_json_body(body)
+                .and_then(|value| value.map_or(Ok(()), |v| config.inspect_json(&v)))
+                .err()
+                .map(|block| block.defense)
+        };
+
+        let cases: [(&[u8], &str); 5] = [
+            (br#"{"role": "user", "role": "admin"}"#, "duplicate-key"),
+            (br#"["not", "an", "object"]"#, "expected-object"),
+            (br#"{"id": 9007199254740993}"#, "unsafe-number"),
+            (br#"{"name": "a\u0000b"}"#, "control-character"),
+            (b"{not json", "non-json-body"),
+        ];
+        for (body, defense) in cases {
+            assert_eq!(parse_block(&relaxed, body), None, "{}", defense);
+            assert_eq!(parse_block(&paranoid, body), Some(defense));
+        }
+        assert_eq!(parse_block(&paranoid, br#"{"id": 9007199254740991, "note": "tab\tok"}"#), None);
+    }
+
+    #[test]
+    fn gzipped_responses_are_redacted_and_recompressed() {
+        let config = PolicyConfig::load(br#"{"response_redact_paths": ["user.ssn", "cards.number"]}"#).unwrap();
+        let body = br#"{"user": {"name": "ada", "ssn": "123-45-6789"}, "cards": [{"number": "4111"}, {"number": "5500"}]}"#;
+
+        let redacted = config.redact_response(&gzip(body).unwrap(), ContentEncoding::Gzip).unwrap();
+        let plain = gunzip(&redacted, 1024).unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&plain).unwrap(), json(
+            r#"{"user": {"name": "ada", "ssn": "[REDACTED]"}, "cards": [{"number": "[REDACTED]"}, {"number": "[REDACTED]"}]}"#));
+
+        let identity = config.redact_response(body, ContentEncoding::Identity).unwrap();
+        assert_eq!(identity, plain);
+        assert_eq!(config.redact_response(br#"{"user": {"name": "ada"}}"#, ContentEncoding::Identity), None);
+
+        // Beyond the decompression bound the response is left alone
+        let bounded = PolicyConfig { max_decompressed_bytes: 16, ..config };
+        assert_eq!(bounded.redact_response(&gzip(body).unwrap(), ContentEncoding::Gzip), None);
+
+        assert_eq!(ContentEncoding::parse(Some("GZIP")), Some(ContentEncoding::Gzip));
+        assert_eq!(ContentEncoding::parse(Some("br")), None);
+    }
+
+    #[test]
+    fn string_value_count_is_bounded() {
+        let config = PolicyConfig::load(br#"{"max_values": 1000, "value_deny_regexes": ["^x+$"]}"#).unwrap();
+        let huge = serde_json::json!({ "tags": vec!["tag"; 100_000] });
+        assert_eq!(config.inspect_json(&huge).unwrap_err().defense, "too-many-values");
+
+        let within = serde_json::json!({ "tags": vec!["tag"; 999], "note": "ok", "count": 7 });
+        assert!(config.inspect_json(&within).is_ok());
+    }
+
+    #[test]
+    fn redaction_can_be_limited_to_response_statuses() {
+        let any_status = PolicyConfig::load(br#"{"response_redact_paths": ["ssn"]}"#).unwrap();
+        assert!(any_status.redacts_status(Some(500)) && any_status.redacts_status(None));
+
+        let ok_only = PolicyConfig::load(br#"{"response_redact_paths": ["ssn"], "response_redact_statuses": [200]}"#).unwrap();
+        assert!(ok_only.redacts_status(Some(200)));
+        assert!(!ok_only.redacts_status(Some(500)));
+        assert!(!ok_only.redacts_status(None));
+
+        assert!(!PolicyConfig::load(br#"{"response_redact_statuses": [200]}"#).unwrap().redacts_status(Some(200)));
+    }
+
+    #[test]
+    fn shared_data_errors_follow_the_failure_policy() {
+        let limit = RateLimit { requests_per_window: 10, window_secs: 60 };
+        let unreadable = || Err(Status::InternalFailure);
+        let unwritable = |_: &[u8], _: Option<u32>| Err(Status::InternalFailure);
+        let empty = || Ok((None, None));
+        let stored = |_: &[u8], _: Option<u32>| Ok(());
+
+        let open = SharedDataFailure::FailOpen;
+        assert!(limit.check("10.0.0.1", 0, open, unreadable, stored).is_ok());
+        assert!(limit.check("10.0.0.1", 0, open, empty, unwritable).is_ok());
+
+        let closed = SharedDataFailure::FailClosed;
+        let block = limit.check("10.0.0.1", 0, closed, unreadable, stored).unwrap_err();
+        assert_eq!(block.defense, "shared-data-unavailable");
+        assert!(limit.check("10.0.0.1", 0, closed, empty, unwritable).is_err());
+        assert!(limit.check("10.0.0.1", 0, closed, empty, stored).is_ok());
+
+        // Contention is not a failure, whichever the policy
+        let contended = |_: &[u8], _: Option<u32>| Err(Status::CasMismatch);
+        assert!(limit.check("10.0.0.1", 0, closed, empty, contended).is_ok());
+    }
+
+    fn encode_binary_policy(settings: &str, sets: &[(u8, &[&str])]) -> Vec<u8> {
+        fn section(out: &mut Vec<u8>, tag: u8, strings: &[&str]) {
+            out.push(tag);
+            out.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+            for s in strings {
+                out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                out.extend_from_slice(s.as_bytes());
+            }
+        }
+        let mut out = BINARY_POLICY_MAGIC.to_vec();
+        section(&mut out, 0, &[settings]);
+        for (tag, strings) in sets {
+            section(&mut out, *tag, strings);
+        }
+        out
+    }
+
+    #[test]
+    fn binary_policy_loads_like_its_json_form() {
+        let binary = encode_binary_policy(r#"{"block_status": 404, "violation_threshold": 1}"#, &[
+            (1, &["User.Password", "ssn"]),
+            (2, &["user.name", "user.password", "ssn"]),
+            (3, &["__proto__"]),
+        ]);
+        let from_binary = PolicyConfig::load(&binary).unwrap();
+        let from_json = PolicyConfig::load(br#"{
+            "block_status": 404, "violation_threshold": 1,
+            "suppression_paths": ["User.Password", "ssn"],
+            "allow_paths": ["user.name", "user.password", "ssn"],
+            "deny_key_names": ["__proto__"]
+        }"#).unwrap();
+
+        assert_eq!(from_binary.suppression_paths, from_json.suppression_paths);
+        assert_eq!(from_binary.allow_paths, from_json.allow_paths);
+        assert_eq!(from_binary.deny_key_names, from_json.deny_key_names);
+        assert_eq!((from_binary.block_status, from_binary.violation_threshold), (404, 1));
+        for body in [r#"{"user": {"name": "a"}}"#, r#"{"user": {"password": "x"}, "ssn": 1}"#, r#"{"__proto__": {}}"#] {
+            assert_eq!(from_binary.inspect_json(&json(body)).err().map(|b| b.defense),
+                       from_json.inspect_json(&json(body)).err().map(|b| b.defense), "{}", body);
+        }
+    }
+
+    #[test]
+    fn malformed_binary_policies_are_rejected() {
+        let mut truncated = encode_binary_policy("{}", &[(1, &["password"])]);
+        truncated.pop();
+        assert!(PolicyConfig::load(&truncated).is_err());
+
+        let unknown = encode_binary_policy("{}", &[(9, &["x"])]);
+        assert!(PolicyConfig::load(&unknown).unwrap_err().contains("section 9"));
+        assert!(PolicyConfig::load(&encode_binary_policy("[1]", &[])).is_err());
+    }
+
+    #[test]
+    fn allowed_arrays_admit_their_elements() {
+        let config = PolicyConfig::load(br#"{"allow_paths": [
+            "id",
+            {"path": "tags", "array": true, "values": ["red", "blue"]},
+            {"path": "items", "array": true},
+            "meta"
+        ]}"#).unwrap();
+        assert!(config.inspect_json(&json(r#"{"id": 1, "tags": ["red", "blue", "red"]}"#)).is_ok());
+        assert!(config.inspect_json(&json(r#"{"items": [{"sku": "a", "qty": 2}, {"sku": "b"}]}"#)).is_ok());
+
+        let block = config.inspect_json(&json(r#"{"tags": ["red", "green"]}"#)).unwrap_err();
+        assert_eq!(block.defense, "value-not-allowed");
+        // Only a path marked as an array admits nested keys
+        assert_eq!(config.inspect_json(&json(r#"{"meta": [{"debug": true}]}"#)).unwrap_err().defense, "antigen-rejected");
+        assert_eq!(config.inspect_json(&json(r#"{"items": {"sku": "a"}}"#)).unwrap_err().defense, "antigen-rejected");
+    }
+
+    #[test]
+    fn most_specific_glob_decides_suppression() {
+        let config = PolicyConfig::load(br#"{
+            "suppression_globs": ["config.*", "config.public.admin*"],
+            "suppression_glob_exceptions": ["config.public.**"]
+        }"#).unwrap();
+
+        let block = config.inspect_json(&json(r#"{"config": {"secret": "x"}}"#)).unwrap_err();
+        assert_eq!(block.rule.as_deref(), Some("config.*"));
+        assert!(config.inspect_json(&json(r#"{"config": {"public": {"theme": "dark"}}}"#)).is_ok());
+        assert!(config.inspect_json(&json(r#"{"settings": {"secret": "x"}}"#)).is_ok());
+
+        // A more specific suppression inside the exception still applies
+        let block = config.inspect_json(&json(r#"{"config": {"public": {"admin_token": "x"}}}"#)).unwrap_err();
+        assert_eq!(block.rule.as_deref(), Some("config.public.admin*"));
+    }
+
+    #[test]
+    fn failed_local_replies_fall_back_to_the_configured_action() {
+        let resetting = PolicyConfig::default();
+        assert_eq!(resetting.action_after_send(Ok(())), (Action::Pause, false));
+        assert_eq!(resetting.action_after_send(Err(Status::InternalFailure)), (Action::Pause, true));
+
+        let continuing = PolicyConfig::load(br#"{"send_failure_action": "continue"}"#).unwrap();
+        assert_eq!(continuing.action_after_send(Ok(())), (Action::Pause, false));
+        assert_eq!(continuing.action_after_send(Err(Status::BadArgument)), (Action::Continue, false));
+    }
+
+    #[test]
+    fn every_value_must_match_value_allow_regex() {
+        let config = PolicyConfig::load(br#"{"value_allow_regex": "[\\x20-\\x7e&&[^<>]]*"}"#).unwrap();
+        assert!(config.compile_errors.is_empty(), "{:?}", config.compile_errors);
+        assert!(config.inspect_json(&json(r#"{"name": "Ada Lovelace", "tags": ["a-b", ""], "age": 36}"#)).is_ok());
+
+        let block = config.inspect_json(&json(r#"{"bio": "<script>"}"#)).unwrap_err();
+        assert_eq!(block.defense, "value-not-allowed");
+        // Whole-value match: a conforming prefix does not excuse the rest
+        assert!(config.inspect_json(&json(r#"{"name": "oké"}"#)).is_err());
+    }
+
+    #[test]
+    fn hosts_are_checked_without_port_or_case() {
+        let config = PolicyConfig::load(br#"{"allowed_hosts": ["API.example.com", "*.tenants.example.com", "::1"]}"#).unwrap();
+        for host in ["api.example.com", "Api.Example.com:8443", "api.example.com.", "a.tenants.example.com", "[::1]:443"] {
+            assert!(config.check_host_allowed(Some(host)).is_ok(), "{}", host);
+        }
+        for host in [Some("evil.com"), Some("api.example.com.evil.com"), Some("tenants.example.com"), Some("xtenants.example.com"), None] {
+            let block = config.check_host_allowed(host).unwrap_err();
+            assert_eq!(block.defense, "host-not-allowed", "{:?}", host);
+        }
+        assert!(PolicyConfig::default().check_host_allowed(None).is_ok());
+    }
+
+    #[test]
+    fn typed_entries_only_fire_for_their_json_type() {
+        let config = PolicyConfig::load(br#"{"suppression_paths": [
+            {"path": "id", "expected_type": "string"},
+            {"path": "flags", "expected_type": "bool"}
+        ]}"#).unwrap();
+        assert_eq!(config.inspect_json(&json(r#"{"id": "1 OR 1=1"}"#)).unwrap_err().rule.as_deref(), Some("id"));
+        assert!(config.inspect_json(&json(r#"{"flags": [1, true]}"#)).is_err());
+        assert!(config.inspect_json(&json(r#"{"id": 42, "flags": [1, 2]}"#)).is_ok());
+
+        let allow = PolicyConfig::load(br#"{"allow_paths": [{"path": "age", "expected_type": "number"}, "name"]}"#).unwrap();
+        assert!(allow.inspect_json(&json(r#"{"age": 36, "name": "ada"}"#)).is_ok());
+        let block = allow.inspect_json(&json(r#"{"age": "36; DROP"}"#)).unwrap_err();
+        assert_eq!(block.defense, "unexpected-type");
+    }
+
+    #[test]
+    fn typed_entries_reject_extra_or_misspelt_constraints() {
+        let combined = PolicyConfig::load(br#"{"suppression_paths": [{"path": "id", "value": "1", "expected_type": "string"}]}"#);
+        assert!(combined.unwrap_err().contains("combines"));
+        assert!(PolicyConfig::load(br#"{"suppression_paths": [{"path": "id", "expected_type": "text"}]}"#).is_err());
+        assert!(PolicyConfig::load(br#"{"allow_paths": [{"path": "age", "expected_typ": "number"}]}"#).is_err());
+
+        let typed = PolicyConfig::load(br#"{"allow_paths": [{"path": "age", "values": ["36"], "expected_type": "string"}]}"#).unwrap();
+        assert_eq!(typed.allow_types.get("age"), Some(&ValueType::String));
+    }
+
+    #[test]
+    fn sha256_hex_matches_known_digests() {
+        assert_eq!(sha256_hex(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(sha256_hex(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+
+    #[test]
+    fn known_bad_body_hashes_are_blocked() {
+        let config = PolicyConfig::load(
+            br#"{"body_hash_denylist": [" BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD "]}"#).unwrap();
+        assert_eq!(config.check_body_hash(b"abc").unwrap_err().defense, "known-bad-body");
+        assert!(config.check_body_hash(b"abc ").is_ok());
+        assert!(PolicyConfig::default().check_body_hash(b"abc").is_ok());
+    }
+
+    #[test]
+    fn evaluation_order_decides_paths_in_both_sets() {
+        let policy = |order: &str| PolicyConfig::load(format!(r#"{{
+            "evaluation_order": "{}",
+            "suppression_paths": ["user.email", "user.ssn"],
+            "suppression_globs": ["user.token*"],
+            "allow_paths": ["user", "user.email", "user.token_hint"],
+            "allow_prefix_depth": 1
+        }}"#, order).as_bytes()).unwrap();
+        let body = json(r#"{"user": {"email": "a@b.c", "token_hint": "x"}}"#);
+
+        let suppress_first = policy("suppress_first");
+        assert_eq!(suppress_first.inspect_json(&body).unwrap_err().rule.as_deref(), Some("user.email"));
+
+        let allow_first = policy("allow_first");
+        assert!(allow_first.inspect_json(&body).is_ok());
+        // Covered only through the allowed parent: not explicit, still suppressed
+        let block = allow_first.inspect_json(&json(r#"{"user": {"ssn": "1"}}"#)).unwrap_err();
+        assert_eq!(block.rule.as_deref(), Some("user.ssn"));
+    }
+
+    #[test]
+    fn uri_length_is_bounded_in_raw_or_decoded_form() {
+        let raw = PolicyConfig::load(br#"{"max_uri_length": 12}"#).unwrap();
+        assert!(raw.inspect_uri("/api?q=abcde").is_ok());
+        assert_eq!(raw.inspect_uri("/api?q=abcdef").unwrap_err().defense, "uri-too-long");
+        assert!(raw.inspect_uri("/a?q=%41%42%43").is_err());
+
+        let decoded = PolicyConfig::load(br#"{"max_uri_length": 12, "uri_length_form": "decoded"}"#).unwrap();
+        assert!(decoded.inspect_uri("/a?q=%41%42%43").is_ok());
+        assert!(decoded.inspect_uri("/api?q=abcdef").is_err());
+    }
+
+    #[test]
+    fn policy_gauges_reflect_the_loaded_rule_counts() {
+        let bytes = br#"{
+            "suppression_paths": ["user.password", {"path": "user.role", "value": "admin"}],
+            "suppression_globs": ["secrets.*"],
+            "allow_paths": ["user.name"],
+            "deny_key_names": ["__proto__", "constructor"],
+            "value_deny_regexes": ["<script"]
+        }"#;
+        let config = PolicyConfig::load(bytes).unwrap();
+        let metrics = Metrics { policy: [Some(1), Some(2), Some(3), Some(4), Some(5)], ..Default::default() };
+        assert_eq!(
+            metrics.policy_samples(&config, bytes.len()),
+            vec![(1, bytes.len() as u64), (2, 3), (3, 1), (4, 2), (5, 1)]
+        );
+
+        let empty = PolicyConfig::load(b"{}").unwrap();
+        let partial = Metrics { policy: [None, Some(2), None, None, None], ..Default::default() };
+        assert_eq!(partial.policy_samples(&empty, 2), vec![(2, 0)]);
+    }
+
+    #[test]
+    fn glob_allow_with_scrub_keeps_allowed_subtrees_and_drops_the_rest() {
+        let config = PolicyConfig::load(br#"{
+            "allow_paths": ["id"],
+            "allow_globs": ["profile.**"],
+            "scrub_unallowed": true
+        }"#).unwrap();
+        let mut body = json(r#"{
+            "id": 7,
+            "profile": {"name": "ada", "links": [{"url": "a"}, {"url": "b"}], "prefs": {"theme": "dark"}},
+            "extra": {"nested": {"deep": 1}},
+            "tracking": "x"
+        }"#);
+        let stripped = config.inspect_json_observed(&body, |_| {}).unwrap();
+        assert!(stripped.tolerated.is_empty());
+        assert_eq!(stripped.scrubbed, vec!["extra".to_string(), "tracking".to_string()]);
+
+        strip_paths(&mut body, "", &stripped.scrubbed);
+        assert_eq!(body, json(r#"{
+            "id": 7,
+            "profile": {"name": "ada", "links": [{"url": "a"}, {"url": "b"}], "prefs": {"theme": "dark"}}
+        }"#));
+
+        // Without scrubbing the same body is rejected, and the glob alone makes the allowlist strict
+        let strict = PolicyConfig::load(br#"{"allow_globs": ["profile.**"]}"#).unwrap();
+        assert_eq!(strict.inspect_json(&json(r#"{"profile":{"name":"ada"},"extra":1}"#)).unwrap_err().defense, "antigen-rejected");
+        assert!(strict.inspect_json(&json(r#"{"profile":{"name":"ada","prefs":{"theme":"dark"}}}"#)).is_ok());
+        assert!(!strict.samples_out(0.99));
+    }
+
+    #[test]
+    fn mutating_requests_need_an_allowed_origin() {
+        let config = PolicyConfig::load(br#"{
+            "require_origin": true,
+            "allowed_origins": ["https://App.example.com/"]
+        }"#).unwrap();
+        let post = Some("POST");
+
+        assert!(config.check_origin(post, Some("https://app.example.com"), None).is_ok());
+        assert!(config.check_origin(post, None, Some("https://app.example.com/settings?tab=1")).is_ok());
+
+        for (origin, referer) in [
+            (Some("https://evil.example.com"), None),
+            (Some("https://app.example.com.evil.com"), None),
+            // Origin is authoritative, so an allowed Referer does not rescue it
+            (Some("https://evil.example.com"), Some("https://app.example.com/")),
+            (None, Some("https://evil.example.com/form")),
+            (Some("null"), None),
+            (None, None),
+            (None, Some("not a url")),
+        ] {
+            let block = config.check_origin(post, origin, referer).unwrap_err();
+            assert_eq!(block.defense, "origin-denied", "{:?} {:?}", origin, referer);
+        }
+
+        // Safe methods and disabled enforcement pass without an origin
+        assert!(config.check_origin(Some("GET"), None, None).is_ok());
+        assert!(PolicyConfig::default().check_origin(post, None, None).is_ok());
+
+        let sandboxed = PolicyConfig::load(br#"{"require_origin": true, "allowed_origins": ["null"]}"#).unwrap();
+        assert!(sandboxed.check_origin(post, Some("null"), None).is_ok());
+    }
+
+    #[test]
+    fn disabled_entries_stay_configured_but_do_not_fire() {
+        let config = PolicyConfig::load(br#"{
+            "suppression_paths": [
+                "token",
+                {"path": "debug", "enabled": false},
+                {"path": "user.role", "value": "admin", "enabled": false},
+                {"path": "trace", "enabled": true}
+            ],
+            "allow_paths": ["token", "debug", "trace", "user", "user.role", {"path": "legacy", "enabled": false}]
+        }"#).unwrap();
+        assert_eq!(config.suppression_entries.len(), 4);
+
+        assert!(config.inspect_json(&json(r#"{"debug": 1, "user": {"role": "admin"}}"#)).is_ok());
+        assert_eq!(config.inspect_json(&json(r#"{"trace": 1}"#)).unwrap_err().rule.as_deref(), Some("trace"));
+        assert_eq!(config.inspect_json(&json(r#"{"token": "t"}"#)).unwrap_err().rule.as_deref(), Some("token"));
+        assert_eq!(config.inspect_json(&json(r#"{"legacy": 1}"#)).unwrap_err().defense, "antigen-rejected");
+
+        let metrics = Metrics { policy: [None, Some(1), Some(2), None, None], ..Default::default() };
+        assert_eq!(metrics.policy_samples(&config, 0), vec![(1, 2), (2, 5)]);
+    }
+
+    #[test]
+    fn decoded_path_segments_are_matched_anywhere() {
+        let config = PolicyConfig::load(br#"{"suppression_path_segments": ["Admin", ".."]}"#).unwrap();
+        for uri in ["/a/admin/b", "/admin", "/a/ADMIN", "/a/%61dmin/b", "/static/%2e%2e/etc", "/a/x%2F..%2Fy", "/a/..%5Cb"] {
+            let block = config.inspect_uri(uri).unwrap_err();
+            assert_eq!(block.defense, "path-segment-denied", "{}", uri);
+        }
+        assert_eq!(config.inspect_uri("/a/admin/b").unwrap_err().rule.as_deref(), Some("admin"));
+        // Whole segments only, and the query string is not a path
+        for uri in ["/administrator", "/a/sysadmin/b", "/a/b?next=/admin", "/a/.../b"] {
+            assert!(config.inspect_uri(uri).is_ok(), "{}", uri);
+        }
+    }
+
+    #[test]
+    fn rate_limits_and_policy_blocks_use_their_own_responses() {
+        let config = PolicyConfig::load(br#"{
+            "block_body_json": true,
+            "block_cors": {"allow_origin": "*"},
+            "rate_limit": {"requests_per_window": 10, "window_secs": 60},
+            "rate_limit_response": {"body": "slow down", "headers": {"X-RateLimit-Policy": "10;w=60"}}
+        }"#).unwrap();
+        let request = RequestInfo { origin: Some("https://app.example.com".into()), ..Default::default() };
+
+        let throttled = config.render_block(&Block::rate_limited(30, "10.0.0.1"), &request, false);
+        assert_eq!(throttled.status, 429);
+        assert_eq!(throttled.headers, vec![
+            ("retry-after".to_string(), "30".to_string()),
+            ("x-leukocyte-defense".to_string(), "rate-limited".to_string()),
+            ("x-ratelimit-policy".to_string(), "10;w=60".to_string()),
+        ]);
+        assert_eq!(throttled.body.as_deref(), Some(&b"slow down"[..]));
+
+        let denied = config.render_block(&sample_block(), &request, false);
+        assert_eq!(denied.status, 403);
+        assert!(!denied.headers.iter().any(|(name, _)| name == "x-ratelimit-policy"));
+        assert!(denied.headers.iter().any(|(name, _)| name == "access-control-allow-origin"));
+
+        // Neither status setting reaches the other subsystem
+        let swapped = PolicyConfig::load(br#"{"block_status": 451, "rate_limit_response": {"status": 503}}"#).unwrap();
+        assert_eq!(swapped.render_block(&Block::rate_limited(1, "10.0.0.1"), &request, false).status, 503);
+        assert_eq!(swapped.render_block(&sample_block(), &request, false).status, 451);
+    }
+
+    #[test]
+    fn vm_configuration_toggles_apply_at_vm_start() {
+        assert_eq!(VmConfig::parse(None), Ok(VmConfig::default()));
+        assert_eq!(VmConfig::parse(Some(b" \n")), Ok(VmConfig::default()));
+        assert!(VmConfig::parse(Some(b"{\"log_level\": \"loud\"}")).is_err());
+
+        let vm = VmConfig::parse(Some(br#"{"log_level": "warn", "shared_data_failure": "fail_closed"}"#)).unwrap();
+        vm.apply_log_level();
+        assert_eq!(log::max_level(), log::LevelFilter::Warn);
+        VmConfig { log_level: Some(VmLogLevel::Trace), ..Default::default() }.apply_log_level();
+        assert_eq!(log::max_level(), log::LevelFilter::Trace);
+
+        // Policies loaded afterwards, including pushed ones and their sub-policies, inherit the toggle
+        let mut root = LeukocyteRoot {
+            config: Rc::new(PolicyConfig::default()),
+            vm,
+            metrics: None,
+            incident_queue: None,
+            config_queue: None,
+        };
+        assert!(root.apply_pushed_config(br#"{
+            "shared_data_failure": "fail_open",
+            "sub_policies": [{"match": {"methods": ["DELETE"]}}]
+        }"#));
+        assert_eq!(root.config.shared_data_failure, SharedDataFailure::FailClosed);
+        assert_eq!(root.config.resolved_sub_policies[0].policy.shared_data_failure, SharedDataFailure::FailClosed);
+    }
+
+    #[test]
+    fn repeated_headers_are_checked_per_occurrence_and_joined() {
+        let request = headers(&[
+            ("x-forwarded-for", "10.0.0.1"),
+            ("Cookie", "session=abc"),
+            ("X-Forwarded-For", "127.0.0.1"),
+            ("cookie", "role=admin"),
+        ]);
+        assert_eq!(group_headers(&request), vec![
+            ("x-forwarded-for".to_string(), vec!["10.0.0.1", "127.0.0.1"]),
+            ("cookie".to_string(), vec!["session=abc", "role=admin"]),
+        ]);
+
+        // A later occurrence is inspected, not just the first
+        let per_occurrence = PolicyConfig::load(br#"{"header_value_deny_regexes": {"X-Forwarded-For": ["^127\\."]}}"#).unwrap();
+        let block = per_occurrence.inspect_headers(&request).unwrap_err();
+        assert_eq!((block.defense, block.rule.as_deref()), ("header-value-denied", Some("x-forwarded-for")));
+
+        // A pattern spanning two occurrences only matches the joined value
+        let spanning = br#"{"header_value_deny_regexes": {"cookie": ["session=abc; role=admin"]}}"#;
+        assert!(PolicyConfig::load(spanning).unwrap().inspect_headers(&request).is_ok());
+        let mut joined = PolicyConfig::load(spanning).unwrap();
+        joined.match_joined_header_values = true;
+        assert_eq!(joined.inspect_headers(&request).unwrap_err().defense, "header-value-denied");
+
+        let schemes = PolicyConfig::load(br#"{"allowed_auth_schemes": ["bearer"]}"#).unwrap();
+        let smuggled = headers(&[("authorization", "Bearer t"), ("Authorization", "Basic dXNlcjpwYXNz")]);
+        assert_eq!(schemes.inspect_headers(&smuggled).unwrap_err().defense, "bad-auth-scheme");
+    }
+
+    #[test]
+    fn decision_header_is_stamped_only_when_enabled() {
+        let request = RequestInfo::default();
+        let off = PolicyConfig::default();
+        let on = PolicyConfig { debug_decision_header: true, ..Default::default() };
+
+        let mut summary = RequestSummary::default();
+        assert_eq!(summary.decision_header(&on), None);
+        summary.finish(&on, &request, None, None);
+        assert_eq!(summary.decision_header(&on), Some("allow"));
+        assert_eq!(summary.decision_header(&off), None);
+
+        let mut scrubbed = RequestSummary { stripped: true, ..Default::default() };
+        scrubbed.finish(&on, &request, None, None);
+        assert_eq!(scrubbed.decision_header(&on), Some("scrub"));
+
+        let mut passed = RequestSummary::default();
+        passed.finish(&on, &request, Some(Passthrough::HealthCheck), None);
+        assert_eq!(passed.decision_header(&on), Some("passthrough:health_check"));
+
+        assert!(!PolicyConfig::load(b"{}").unwrap().debug_decision_header);
+    }
+
+    #[test]
+    fn required_and_optional_paths_form_a_strict_model() {
+        let config = PolicyConfig::load(br#"{
+            "required_paths": ["user", "user.id", "Action"],
+            "allow_paths": ["user.name", "note"]
+        }"#).unwrap();
+
+        // Required paths alone, and with any of the optional ones
+        assert!(config.inspect_json(&json(r#"{"user": {"id": 1}, "action": "buy"}"#)).is_ok());
+        assert!(config.inspect_json(&json(r#"{"user": {"id": 1, "name": "ada"}, "action": "buy", "note": "x"}"#)).is_ok());
+
+        let block = config.inspect_json(&json(r#"{"user": {"name": "ada"}, "action": "buy"}"#)).unwrap_err();
+        assert_eq!((block.defense, block.rule.as_deref()), ("missing-required-path", Some("user.id")));
+        assert_eq!(config.inspect_json(&json("{}")).unwrap_err().defense, "missing-required-path");
+
+        let block = config.inspect_json(&json(r#"{"user": {"id": 1}, "action": "buy", "admin": true}"#)).unwrap_err();
+        assert_eq!((block.defense, block.rule.as_deref()), ("antigen-rejected", Some("admin")));
+
+        // An empty body lacks the required paths whatever empty_body_action says
+        assert_eq!(config.check_empty_body(0).unwrap_err().defense, "empty-body");
+        assert!(config.check_empty_body(2).is_ok());
+        // So does a request with no body at all, which only the header phase sees
+        let block = config.check_bodyless(true).unwrap_err();
+        assert_eq!((block.defense, block.rule), ("empty-body", None));
+    }
+
+    #[test]
+    fn oversized_reports_are_truncated_everywhere() {
+        let config = PolicyConfig::load(br#"{"reveal_match": true, "max_report_bytes": 10}"#).unwrap();
+        assert_eq!(config.report("short"), "short");
+        assert_eq!(config.report("abcdefghijklmnop"), "abcdefghij...");
+        // Never splits a character: "é" is two bytes straddling the cap
+        assert_eq!(config.report("abcdefghié"), "abcdefghi...");
+
+        let long_path = format!("/api/{}", "x".repeat(500));
+        let request = RequestInfo { route: long_path.clone(), path: Some(long_path), ..Default::default() };
+        let block = sample_block().with_rule("a.".repeat(300));
+
+        let reply = config.render_block(&block, &request, false);
+        assert_eq!(String::from_utf8(reply.body.unwrap()).unwrap(), "Access Denied: Pathogen Suppressed (a.a.a.a.a....)");
+        let record: serde_json::Value = serde_json::from_slice(&config.incident_record(&block, &request, 0)).unwrap();
+        assert_eq!((record["path"].as_str(), record["rule"].as_str()), (Some("/api/xxxxx..."), Some("a.a.a.a.a....")));
+        assert_eq!(config.recent_block_entry(&block, &request, 0)["path"], "/api/xxxxx...");
+        assert!(config.block_log_line(&block, &request).ends_with(" path=/api/xxxxx..."));
+
+        assert_eq!(PolicyConfig::default().max_report_bytes, 256);
+    }
+
+    #[test]
+    fn depth_entries_fire_only_at_their_segment_depth() {
+        let config = PolicyConfig::load(br#"{"suppression_paths": [
+            {"path": "Token", "depth": 1},
+            {"path": "secret", "depth": 2}
+        ]}"#).unwrap();
+        assert_eq!(config.inspect_json(&json(r#"{"token": "t"}"#)).unwrap_err().rule.as_deref(), Some("token"));
+        assert!(config.inspect_json(&json(r#"{"metadata": {"token": "t"}}"#)).is_ok());
+
+        assert!(config.inspect_json(&json(r#"{"auth": {"secret": 1}}"#)).is_err());
+        assert!(config.inspect_json(&json(r#"{"items": [{"secret": 1}]}"#)).is_err());
+        assert!(config.inspect_json(&json(r#"{"secret": 1, "a": {"b": {"secret": 1}}, "xsecret": {"y": 1}}"#)).is_ok());
+    }
+
+    #[test]
+    fn malformed_suppression_entries_fail_the_load() {
+        for entry in [
+            r#"{"path": "token", "depth": -1}"#,
+            r#"{"path": "token", "depth": "1"}"#,
+            r#"{"path": "token", "depht": 1}"#,
+            r#"{"path": "token", "value": "t", "depth": 1}"#,
+        ] {
+            let config = format!(r#"{{"suppression_paths": [{}]}}"#, entry);
+            assert!(PolicyConfig::load(config.as_bytes()).is_err(), "{}", entry);
+        }
+
+        let combined = PolicyConfig::load(br#"{"suppression_paths": [{"path": "token", "value": "t", "depth": 1}]}"#);
+        assert!(combined.unwrap_err().contains("combines"));
+
+        let plain = PolicyConfig::load(br#"{"suppression_paths": [
+            {"path": "Token"}, {"path": "off", "enabled": false}, {"path": "nothing", "value": null}
+        ]}"#).unwrap();
+        assert!(plain.suppression_paths.contains("token"));
+        assert!(!plain.suppression_paths.contains("off"));
+        assert_eq!(plain.suppression_values["nothing"], vec![serde_json::Value::Null]);
+    }
+
+    #[test]
+    fn self_test_reports_status_and_rule_counts() {
+        let config = PolicyConfig::load(br#"{
+            "self_test": {"header": "X-Leukocyte-Selftest"},
+            "suppression_paths": ["password", "ssn"],
+            "allow_paths": ["user.name"],
+            "value_deny_regexes": ["(unclosed", "<script"]
+        }"#).unwrap();
+        let probe = |value: Option<&str>| config.is_self_test(|name| {
+            assert_eq!(name, "x-leukocyte-selftest");
+            value.map(str::to_string)
+        });
+        assert!(probe(Some("1")));
+        assert!(!probe(Some("0")));
+        assert!(!probe(None));
+
+        let report: serde_json::Value = serde_json::from_slice(&config.self_test_report()).unwrap();
+        assert_eq!(report["rules"], json(r#"{"suppression": 2, "allow": 1, "deny_key_names": 0, "value_deny": 2}"#));
+        assert!(report["status"].as_str().unwrap().starts_with("error: 1 invalid rule(s)"));
+
+        // Off by default: no header triggers it
+        assert!(!PolicyConfig::default().is_self_test(|_| Some("1".to_string())));
+        let ok: serde_json::Value = serde_json::from_slice(&PolicyConfig::default().self_test_report()).unwrap();
+        assert_eq!(ok["status"], "ok");
+    }
+
+    #[test]
+    fn query_params_merge_into_the_body_namespace() {
+        let config = PolicyConfig::load(br#"{
+            "merge_query_params": true,
+            "suppression_paths": ["query.token", "user.password"]
+        }"#).unwrap();
+        let query = config.query_document(Some("/login?token=abc&lang=en#top"));
+        assert_eq!(query, Some(json(r#"{"token": "abc", "lang": "en"}"#)));
+        assert_eq!(config.query_document(Some("/login")), None);
+        assert_eq!(PolicyConfig::default().query_document(Some("/login?token=abc")), None);
+
+        // Caught from the query alone and alongside a body
+        let alone = with_query(None, query.as_ref()).unwrap();
+        assert_eq!(config.inspect_json(&alone).unwrap_err().rule.as_deref(), Some("query.token"));
+        let body = json(r#"{"user": {"name": "ada"}}"#);
+        let merged = with_query(Some(&body), query.as_ref()).unwrap();
+        assert_eq!(merged["user"]["name"], "ada");
+        assert_eq!(config.inspect_json(&merged).unwrap_err().rule.as_deref(), Some("query.token"));
+
+        // A body `query` key cannot mask the params, nor the params the body
+        let decoy = json(r#"{"query": {"page": 1}}"#);
+        let merged = with_query(Some(&decoy), query.as_ref()).unwrap();
+        assert!(config.inspect_json(&merged).is_err());
+        let clean = config.query_document(Some("/login?lang=en"));
+        let hidden = json(r#"{"query": {"token": "abc"}}"#);
+        assert!(config.inspect_json(&with_query(Some(&hidden), clean.as_ref()).unwrap()).is_err());
+
+        // Without params the body is inspected as is
+        assert!(matches!(with_query(Some(&body), None), Some(Cow::Borrowed(_))));
+    }
+
+    #[test]
+    fn foreign_query_params_block_even_when_scrubbing() {
+        let config = PolicyConfig::load(br#"{
+            "merge_query_params": true,
+            "allow_paths": ["user", "user.name", "query", "query.lang"],
+            "scrub_unallowed": true
+        }"#).unwrap();
+        let body = json(r#"{"user": {"name": "ada", "role": "admin"}}"#);
+
+        // Only body paths are scrubbed: the upstream gets `/profile?lang=en` unchanged
+        let query = config.query_document(Some("/profile?lang=en"));
+        let stripped = config.inspect_json_observed(&with_query(Some(&body), query.as_ref()).unwrap(), |_| {}).unwrap();
+        assert_eq!(stripped.scrubbed, vec!["user.role".to_string()]);
+
+        // A foreign param would reach the upstream in `:path`, so it blocks instead
+        let query = config.query_document(Some("/profile?lang=en&debug=1"));
+        let block = config.inspect_json(&with_query(Some(&body), query.as_ref()).unwrap()).unwrap_err();
+        assert_eq!((block.defense, block.rule.as_deref()), ("antigen-rejected", Some("query.debug")));
+        let block = config.inspect_json(&with_query(None, query.as_ref()).unwrap()).unwrap_err();
+        assert_eq!(block.rule.as_deref(), Some("query.debug"));
+    }
+
+    #[test]
+    fn expect_continue_uploads_release_headers_and_still_judge_the_body() {
+        let config = PolicyConfig::load(br#"{"buffering_mode": "pause", "suppression_paths": ["is_admin"]}"#).unwrap();
+        let request = headers(&[("content-type", "application/json"), ("Expect", " 100-Continue ")]);
+        assert!(expects_continue(&request));
+        assert!(!expects_continue(&headers(&[("expect", "something-else")])));
+
+        // 1. Headers pass untouched and are released, so the interim 100 can be sent
+        assert!(config.inspect_headers(&request).is_ok());
+        assert_eq!(config.headers_action(true, expects_continue(&request)), Action::Continue);
+
+        // 2. The body then arrives in chunks and is buffered to the end
+        let body = br#"{"name":"a","is_admin":true}"#;
+        assert!(config.check_buffer(12).unwrap());
+        assert!(config.check_buffer(body.len()).unwrap());
+
+        // 3. The full body is judged before any of it is forwarded
+        let parsed = config.parse_json_body(body).unwrap().unwrap();
+        assert_eq!(config.inspect_json(&parsed).unwrap_err().defense, "methylated");
+
+        // Without the header, pause mode keeps holding headers
+        assert_eq!(config.headers_action(true, false), Action::Pause);
+    }
+
+    #[test]
+    fn scrubbed_bodies_keep_key_order_unless_configured() {
+        let body = br#"{"zeta": 1, "id": 7, "extra": {"x": 1}, "profile": {"b": 2, "a": 1}, "alpha": true}"#;
+        let scrub = |settings: &str| {
+            let config = PolicyConfig::load(format!(
+                r#"{{"allow_paths": ["zeta", "id", "alpha"], "allow_globs": ["profile.**"], "scrub_unallowed": true{}}}"#,
+                settings).as_bytes()).unwrap();
+            let mut parsed = config.parse_json_body(body).unwrap().unwrap();
+            let stripped = config.inspect_json_observed(&parsed, |_| {}).unwrap();
+            strip_paths(&mut parsed, "", &stripped.scrubbed);
+            String::from_utf8(parser_for(Some("application/json")).encode(&config, &parsed)).unwrap()
+        };
+
+        assert_eq!(scrub(""), r#"{"zeta":1,"id":7,"profile":{"b":2,"a":1},"alpha":true}"#);
+        assert_eq!(scrub(r#", "preserve_key_order": false"#), r#"{"alpha":true,"id":7,"profile":{"a":1,"b":2},"zeta":1}"#);
+        let pretty = scrub(r#", "minify": false"#);
+        assert!(pretty.starts_with("{\n  \"zeta\": 1,\n  \"id\": 7"), "{}", pretty);
+    }
+}
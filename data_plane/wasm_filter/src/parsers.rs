@@ -0,0 +1,158 @@
+//! Body parsers that normalize a request body into a JSON value, so the
+//! suppression, allowlist and value rules run unchanged on every format.
+//!
+//! Formats the rules cannot express as a document (CSV columns, multipart
+//! parts, gRPC-Web frames) keep their dedicated inspectors instead.
+
+use crate::{percent_decode, Block, PolicyConfig};
+use serde_json::{Map, Value};
+
+pub(crate) trait BodyParser {
+    /// Short name for logs and tests.
+    fn name(&self) -> &'static str;
+
+    /// Whether this parser handles the declared content-type.
+    fn accepts(&self, content_type: &str) -> bool;
+
+    /// The normalized document, or `None` when there is nothing to inspect.
+    fn parse(&self, config: &PolicyConfig, body: &[u8]) -> Result<Option<Value>, Block>;
+
+    /// Re-encodes a normalized document after suppressed paths were stripped.
+    fn encode(&self, config: &PolicyConfig, value: &Value) -> Vec<u8>;
+}
+
+pub(crate) struct JsonParser;
+
+impl BodyParser for JsonParser {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn accepts(&self, content_type: &str) -> bool {
+        crate::is_json_content_type(content_type)
+    }
+
+    fn parse(&self, config: &PolicyConfig, body: &[u8]) -> Result<Option<Value>, Block> {
+        config.parse_json_body(body)
+    }
+
+    fn encode(&self, config: &PolicyConfig, value: &Value) -> Vec<u8> {
+        config.serialize_json(value)
+    }
+}
+
+/// `application/x-www-form-urlencoded`. Each field becomes a top-level key;
+/// a repeated field becomes an array of its values, in order.
+pub(crate) struct FormParser;
+
+impl BodyParser for FormParser {
+    fn name(&self) -> &'static str {
+        "form"
+    }
+
+    fn accepts(&self, content_type: &str) -> bool {
+        crate::is_form_content_type(content_type)
+    }
+
+    fn parse(&self, _config: &PolicyConfig, body: &[u8]) -> Result<Option<Value>, Block> {
+        if body.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Value::Object(form_fields(&String::from_utf8_lossy(body)))))
+    }
+
+    fn encode(&self, _config: &PolicyConfig, value: &Value) -> Vec<u8> {
+        let Some(fields) = value.as_object() else { return Vec::new() };
+        let mut pairs = Vec::new();
+        for (name, value) in fields {
+            let values = match value {
+                Value::Array(values) => values.iter().collect(),
+                other => vec![other],
+            };
+            for value in values {
+                let text = value.as_str().map_or_else(|| value.to_string(), str::to_string);
+                pairs.push(format!("{}={}", form_encode(name), form_encode(&text)));
+            }
+        }
+        pairs.join("&").into_bytes()
+    }
+}
+
+/// Parsers in lookup order.
+static BODY_PARSERS: [&(dyn BodyParser + Sync); 2] = [&JsonParser, &FormParser];
+
+/// The parser for a content-type. Anything unclaimed, including a missing
+/// content-type, falls back to JSON, as bodies always have.
+pub(crate) fn parser_for(content_type: Option<&str>) -> &'static dyn BodyParser {
+    content_type
+        .and_then(|ct| BODY_PARSERS.iter().find(|p| p.accepts(ct)))
+        .map_or(&JsonParser, |p| *p)
+}
+
+/// Decoded `name=value&...` pairs, shared by form bodies and query strings.
+pub(crate) fn form_fields(text: &str) -> Map<String, Value> {
+    let mut fields = Map::new();
+    for pair in text.split('&').filter(|p| !p.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let name = form_decode(name);
+        let value = Value::String(form_decode(value));
+        match fields.get_mut(&name) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+            None => {
+                fields.insert(name, value);
+            }
+        }
+    }
+    fields
+}
+
+fn form_decode(text: &str) -> String {
+    percent_decode(&text.replace('+', " "))
+}
+
+fn form_encode(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'*' => out.push(byte as char),
+            b' ' => out.push('+'),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_dispatches_by_content_type_with_json_fallback() {
+        assert_eq!(parser_for(Some("application/json")).name(), "json");
+        assert_eq!(parser_for(Some("application/vnd.api+json")).name(), "json");
+        assert_eq!(parser_for(Some("application/x-www-form-urlencoded; charset=utf-8")).name(), "form");
+        assert_eq!(parser_for(Some("text/plain")).name(), "json");
+        assert_eq!(parser_for(None).name(), "json");
+    }
+
+    #[test]
+    fn form_bodies_normalize_and_round_trip() {
+        let config = PolicyConfig::default();
+        let parsed = FormParser.parse(&config, b"user=ada+l&role=admin&tag=a&tag=b%26c&flag").unwrap().unwrap();
+        assert_eq!(parsed, serde_json::json!({
+            "user": "ada l", "role": "admin", "tag": ["a", "b&c"], "flag": ""
+        }));
+
+        let encoded = FormParser.encode(&config, &parsed);
+        assert_eq!(FormParser.parse(&config, &encoded).unwrap().unwrap(), parsed);
+        assert_eq!(FormParser.parse(&config, b"").unwrap(), None);
+    }
+
+    #[test]
+    fn form_fields_meet_json_rules() {
+        let config = PolicyConfig::load(br#"{"suppression_paths": ["password"]}"#).unwrap();
+        let parsed = FormParser.parse(&config, b"user=ada&password=hunter2").unwrap().unwrap();
+        assert_eq!(config.inspect_json_observed(&parsed, |_| {}).unwrap_err().defense, "methylated");
+    }
+}